@@ -4,9 +4,8 @@ use crate::shape::AABB;
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 use crate::common::{surrounding_box, Axis};
-use std::borrow::{BorrowMut, Borrow};
-use crate::sort::quick_select;
 use crate::vec3::Vec3;
+use std::thread;
 
 pub(crate) struct BvhNode{
     src_objects:Option<Vec<Arc<dyn Hittable>>>,
@@ -69,67 +68,389 @@ impl Hittable for BvhNode {
         todo!()
     }
 }
+//构建 BVH 的参数 代价只看相对大小
+#[derive(Copy, Clone)]
+pub(crate) struct BvhConfig{
+    pub(crate) max_leaf_size:usize,
+    //访问一个内部节点(做一次包围盒测试)的代价
+    //这里的包围盒测试要递归 clone Arc 实测比求一个三角形还贵
+    pub(crate) traversal_cost:f64,
+    //和一个物体求交的代价
+    pub(crate) intersection_cost:f64,
+    pub(crate) bins:usize,
+    //构建时最多用的线程数
+    pub(crate) threads:usize,
+}
+
+//子树里的物体超过这个数量才拆到新线程上构建
+const PARALLEL_SUBTREE_SIZE:usize = 4096;
+//物体超过这个数量时 分桶和求包围盒分块并行
+const PARALLEL_BIN_SIZE:usize = 65536;
+
+impl BvhConfig{
+    pub(crate) fn new() -> Self{
+        Self{
+            max_leaf_size: 8,
+            traversal_cost: 1.0,
+            intersection_cost: 0.5,
+            bins: 16,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+fn objects_box(objs:&[Arc<dyn Hittable>]) -> AABB{
+    let mut total_box = objs[0].bounding_box().unwrap();
+    for obj in objs[1..].iter(){
+        total_box = surrounding_box(total_box, obj.bounding_box().unwrap()).unwrap();
+    }
+    total_box
+}
+
+const AXES:[Axis;3] = [Axis::X, Axis::Y, Axis::Z];
+
 impl BvhNode{
-    pub(crate) fn form(rc_objects: &mut [Arc<dyn Hittable>],t0:f64,t1:f64) -> Option<BvhNode>{
-        if rc_objects.len() <= 5{
-            let mut vec = vec![];
-            let mut total_box = None;
-            let mut first = true;
-            let mut objs_num = 0;
-            for obj in rc_objects{
-                let tmp_box = obj.clone().bounding_box();
-                objs_num += 1;
-                vec.push(obj.clone());
-                if first {
-                    total_box = tmp_box;
-                    first = false;
+    pub(crate) fn form_with(rc_objects: &mut [Arc<dyn Hittable>],config:&BvhConfig) -> Option<BvhNode>{
+        if rc_objects.is_empty() {
+            return None;
+        }
+        Some(Self::build_sah(rc_objects, config, config.threads.max(1)))
+    }
+
+    fn leaf(rc_objects: &[Arc<dyn Hittable>]) -> Self{
+        Self{
+            src_objects: Some(rc_objects.to_vec()),
+            contains_objs: rc_objects.len() as i32,
+            bbox: Some(objects_box(rc_objects)),
+            left: None,
+            right: None,
+        }
+    }
+
+    fn interior(left:BvhNode, right:BvhNode) -> Self{
+        Self{
+            src_objects: None,
+            contains_objs: left.contains_objs + right.contains_objs,
+            bbox: surrounding_box(left.bbox.unwrap(), right.bbox.unwrap()),
+            left: Some(Arc::new(left)),
+            right: Some(Arc::new(right)),
+        }
+    }
+
+    //叶子节点里的物体 中间节点返回 None
+    pub(crate) fn leaf_objects(&self) -> Option<&[Arc<dyn Hittable>]>{
+        self.src_objects.as_deref()
+    }
+
+    //物体中心按轴分进若干个桶 在桶的边界里找 SAH 代价最小的划分
+    //代价 = 遍历代价 + (左边面积 * 左边数量 + 右边面积 * 右边数量) / 父节点面积 * 求交代价
+    //threads 是这棵子树还能用的线程数 大的子树左右两边分到不同线程上构建
+    fn build_sah(rc_objects: &mut [Arc<dyn Hittable>],config:&BvhConfig,threads:usize) -> Self{
+        let n = rc_objects.len();
+        if n == 1 {
+            return Self::leaf(rc_objects);
+        }
+        let bin_threads = if n >= PARALLEL_BIN_SIZE { threads } else { 1 };
+        let (bbox, centroid_range) = Self::bounds(rc_objects, bin_threads);
+        let parent_area = bbox.surface_area();
+        let bins = config.bins.max(2);
+
+        //最优的 (代价, 轴, 划分的桶)
+        let mut best:Option<(f64, usize, usize)> = None;
+        for (ai, axis) in AXES.iter().enumerate(){
+            let (cmin, cmax) = centroid_range[ai];
+            if cmax - cmin <= 0.0 {
+                continue;
+            }
+            let (counts, boxes) = Self::bin_objects(rc_objects, axis, cmin, cmax, bins, bin_threads);
+            //从右往左累加 right_area[i] 是第 i 个桶及右边所有桶的面积
+            let mut right_area = vec![0.0; bins];
+            let mut right_count = vec![0usize; bins];
+            let mut acc:Option<AABB> = None;
+            let mut cnt = 0;
+            for i in (1..bins).rev(){
+                acc = Self::merge(acc, boxes[i]);
+                cnt += counts[i];
+                right_area[i] = acc.map_or(0.0, |b| b.surface_area());
+                right_count[i] = cnt;
+            }
+            let mut acc:Option<AABB> = None;
+            let mut cnt = 0;
+            for split in 1..bins{
+                acc = Self::merge(acc, boxes[split - 1]);
+                cnt += counts[split - 1];
+                if cnt == 0 || right_count[split] == 0 {
                     continue;
                 }
-                total_box = surrounding_box(total_box.unwrap(),tmp_box.unwrap());
+                let left_area = acc.map_or(0.0, |b| b.surface_area());
+                let cost = config.traversal_cost + config.intersection_cost *
+                    (left_area * cnt as f64 + right_area[split] * right_count[split] as f64) / parent_area;
+                if !matches!(best, Some((c, _, _)) if c <= cost) {
+                    best = Some((cost, ai, split));
+                }
             }
-            return Some(Self{
-                src_objects: Some(vec),
-                contains_objs: objs_num,
-                bbox: total_box,
-                left: None,
-                right: None,
-            })
-        }
-        let mut max_box = None;
-        let mut first = true;
-        //计算包围盒 优先划分 长度比较长的包围盒
-        for v in rc_objects.iter(){
-            if first {
-                max_box = v.bounding_box();
-                first = false;
-                continue;
+        }
+
+        let leaf_cost = config.intersection_cost * n as f64;
+        let mid = match best {
+            Some((cost, ai, split)) => {
+                if n <= config.max_leaf_size && leaf_cost <= cost {
+                    return Self::leaf(rc_objects);
+                }
+                let axis = &AXES[ai];
+                let (cmin, cmax) = centroid_range[ai];
+                let mut mid = 0;
+                for i in 0..n{
+                    if Self::bin_index(rc_objects[i].get_center_point(axis), cmin, cmax, bins) < split {
+                        rc_objects.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+                mid
             }
-            max_box = surrounding_box(max_box.unwrap(),v.bounding_box().unwrap())
-        }
-        let x_gap = (max_box.unwrap().maximum.x - max_box.unwrap().minimum.x).abs();
-        let y_gap = (max_box.unwrap().maximum.y - max_box.unwrap().minimum.y).abs();
-        let z_gap = (max_box.unwrap().maximum.z - max_box.unwrap().minimum.z).abs();
-        let axis;
-        if x_gap >= y_gap && x_gap>= z_gap{
-            axis = Axis::X;
-        }else if y_gap >= z_gap && y_gap >= x_gap{
-            axis = Axis::Y;
-        }else {
-            axis = Axis::Z;
-        }
-        let mut mid = (rc_objects.len() / 2 + 1) as usize;
-        mid = quick_select(rc_objects, mid,axis.borrow());
-        let left = Self::form(rc_objects[..mid].borrow_mut(), t0,t1);
-        let right = Self::form(rc_objects[mid..].borrow_mut(),  t0,t1);
-        let xbox = surrounding_box(left.as_ref().unwrap().bounding_box().unwrap(),
-                                   right.as_ref().unwrap().bounding_box().unwrap());
-        let total_num = left.as_ref().unwrap().contains_objs + right.as_ref().unwrap().contains_objs;
-        Some(Self{
-            src_objects: None,
-            contains_objs: total_num,
-            bbox: xbox,
-            left: Some(Arc::new(left.unwrap())),
-            right: Some(Arc::new(right.unwrap()))
+            //所有物体中心重合 分不开了
+            None => {
+                if n <= config.max_leaf_size {
+                    return Self::leaf(rc_objects);
+                }
+                n / 2
+            }
+        };
+        let (left_objs, right_objs) = rc_objects.split_at_mut(mid);
+        if threads > 1 && n >= PARALLEL_SUBTREE_SIZE {
+            let left_threads = threads / 2;
+            let (left, right) = thread::scope(|scope| {
+                let handle = scope.spawn(|| Self::build_sah(left_objs, config, left_threads));
+                let right = Self::build_sah(right_objs, config, threads - left_threads);
+                (handle.join().unwrap(), right)
+            });
+            return Self::interior(left, right);
+        }
+        let left = Self::build_sah(left_objs, config, 1);
+        let right = Self::build_sah(right_objs, config, 1);
+        Self::interior(left, right)
+    }
+
+    //把物体分成 threads 块 每块在自己的线程里算 f
+    fn par_chunks<T:Send>(objs:&[Arc<dyn Hittable>], threads:usize, f:impl Fn(&[Arc<dyn Hittable>]) -> T + Sync) -> Vec<T>{
+        if threads <= 1 {
+            return vec![f(objs)];
+        }
+        let chunk = objs.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let handles:Vec<_> = objs.chunks(chunk).map(|c| {
+                let f = &f;
+                scope.spawn(move || f(c))
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
         })
     }
-}
\ No newline at end of file
+
+    //所有物体的包围盒 和物体中心在每个轴上的范围
+    fn bounds(objs:&[Arc<dyn Hittable>], threads:usize) -> (AABB, [(f64, f64);3]){
+        let parts = Self::par_chunks(objs, threads, |chunk| {
+            let mut range = [(f64::MAX, f64::MIN); 3];
+            for obj in chunk.iter(){
+                for (ai, axis) in AXES.iter().enumerate(){
+                    let c = obj.get_center_point(axis);
+                    range[ai] = (range[ai].0.min(c), range[ai].1.max(c));
+                }
+            }
+            (objects_box(chunk), range)
+        });
+        let mut bbox = parts[0].0;
+        let mut range = parts[0].1;
+        for (b, r) in parts[1..].iter(){
+            bbox = surrounding_box(bbox, *b).unwrap();
+            for ai in 0..3{
+                range[ai] = (range[ai].0.min(r[ai].0), range[ai].1.max(r[ai].1));
+            }
+        }
+        (bbox, range)
+    }
+
+    //每个桶里物体的数量和包围盒
+    fn bin_objects(objs:&[Arc<dyn Hittable>], axis:&Axis, cmin:f64, cmax:f64, bins:usize, threads:usize) -> (Vec<usize>, Vec<Option<AABB>>){
+        let parts = Self::par_chunks(objs, threads, |chunk| {
+            let mut counts = vec![0usize; bins];
+            let mut boxes:Vec<Option<AABB>> = vec![None; bins];
+            for obj in chunk.iter(){
+                let b = Self::bin_index(obj.get_center_point(axis), cmin, cmax, bins);
+                counts[b] += 1;
+                boxes[b] = Self::merge(boxes[b], obj.bounding_box());
+            }
+            (counts, boxes)
+        });
+        let mut counts = vec![0usize; bins];
+        let mut boxes:Vec<Option<AABB>> = vec![None; bins];
+        for (c, b) in parts.iter(){
+            for i in 0..bins{
+                counts[i] += c[i];
+                boxes[i] = Self::merge(boxes[i], b[i]);
+            }
+        }
+        (counts, boxes)
+    }
+
+    fn bin_index(c:f64, cmin:f64, cmax:f64, bins:usize) -> usize{
+        let b = ((c - cmin) / (cmax - cmin) * bins as f64) as usize;
+        b.min(bins - 1)
+    }
+
+    fn merge(a:Option<AABB>, b:Option<AABB>) -> Option<AABB>{
+        match (a, b) {
+            (Some(a), Some(b)) => { surrounding_box(a, b) }
+            (a, None) => { a }
+            (None, b) => { b }
+        }
+    }
+}
+
+//展平后的节点 叶子的 count > 0 objects[offset..offset + count] 是它的物体
+//内部节点的左孩子紧跟在自己后面 offset 是右孩子的下标
+#[derive(Copy, Clone)]
+struct LinearNode{
+    bbox:AABB,
+    offset:u32,
+    count:u32,
+    //左右孩子中心分得最开的轴 射线在这个轴上是负方向时先访问右孩子
+    axis:u8,
+}
+
+//数组存储的 BVH 用栈遍历 按射线方向由近到远访问孩子
+pub(crate) struct LinearBvh{
+    nodes:Vec<LinearNode>,
+    objects:Vec<Arc<dyn Hittable>>,
+    //最深的叶子的深度 遍历栈最多压这么多个节点
+    depth:usize,
+}
+
+//树不超过这个深度时遍历栈放在栈上 更深的树(比如 SAH 每层只分出一个离群的物体)用堆上的栈
+const STACK_SIZE:usize = 64;
+
+impl LinearBvh{
+    pub(crate) fn form(rc_objects: &mut [Arc<dyn Hittable>],config:&BvhConfig) -> Option<Self>{
+        let tree = BvhNode::form_with(rc_objects, config)?;
+        Some(Self::from_tree(&tree))
+    }
+
+    pub(crate) fn from_tree(tree:&BvhNode) -> Self{
+        let mut bvh = Self{
+            nodes: vec![],
+            objects: vec![],
+            depth: 0,
+        };
+        bvh.flatten(tree, 0);
+        bvh
+    }
+
+    fn flatten(&mut self, node:&BvhNode, depth:usize) -> usize{
+        let index = self.nodes.len();
+        self.depth = self.depth.max(depth);
+        let bbox = node.bbox.unwrap();
+        match node.src_objects {
+            Some(ref objs) => {
+                self.nodes.push(LinearNode{ bbox, offset: self.objects.len() as u32, count: objs.len() as u32, axis: 0 });
+                self.objects.extend(objs.iter().cloned());
+            }
+            None => {
+                let left = node.left.as_ref().unwrap();
+                let right = node.right.as_ref().unwrap();
+                let lc = left.bbox.unwrap().minimum + left.bbox.unwrap().maximum;
+                let rc = right.bbox.unwrap().minimum + right.bbox.unwrap().maximum;
+                let d = rc - lc;
+                let (dx, dy, dz) = (d.x.abs(), d.y.abs(), d.z.abs());
+                let axis = if dx >= dy && dx >= dz { 0 } else if dy >= dz { 1 } else { 2 };
+                self.nodes.push(LinearNode{ bbox, offset: 0, count: 0, axis });
+                self.flatten(left, depth + 1);
+                let second = self.flatten(right, depth + 1);
+                self.nodes[index].offset = second as u32;
+            }
+        }
+        index
+    }
+}
+
+//射线方向的倒数提前算好 每个节点只做乘法
+fn hit_box(b:&AABB, origin:Vec3, inv_d:Vec3, t_min:f64, t_max:f64) -> bool{
+    let t_in = (b.minimum - origin) * inv_d;
+    let t_out = (b.maximum - origin) * inv_d;
+    let t0 = f64::max(f64::max(f64::min(t_in.x, t_out.x), f64::min(t_in.y, t_out.y)), f64::max(f64::min(t_in.z, t_out.z), t_min));
+    let t1 = f64::min(f64::min(f64::max(t_in.x, t_out.x), f64::max(t_in.y, t_out.y)), f64::min(f64::max(t_in.z, t_out.z), t_max));
+    t0 <= t1
+}
+
+impl Debug for LinearBvh {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"LinearBvh(nodes: {}, objects: {})",self.nodes.len(),self.objects.len())
+    }
+}
+
+impl Hittable for LinearBvh {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let dir = ray.direction();
+        let origin = ray.origin();
+        let inv_d = Vec3::form(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let dir_neg = [inv_d.x < 0.0, inv_d.y < 0.0, inv_d.z < 0.0];
+        let mut small_stack = [0usize; STACK_SIZE];
+        let mut large_stack;
+        let stack:&mut [usize] = if self.depth <= STACK_SIZE {
+            &mut small_stack
+        } else {
+            large_stack = vec![0usize; self.depth];
+            &mut large_stack
+        };
+        let mut top = 0;
+        let mut index = 0;
+        let mut closest = t_max;
+        let mut is_hit = false;
+        loop {
+            let node = &self.nodes[index];
+            if hit_box(&node.bbox, origin, inv_d, t_min, closest) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for obj in self.objects[start..start + node.count as usize].iter(){
+                        if obj.hit(ray, t_min, closest, rec) {
+                            closest = rec.t;
+                            is_hit = true;
+                        }
+                    }
+                } else {
+                    //先访问离射线起点近的孩子 远的压栈
+                    let (near, far) = if dir_neg[node.axis as usize] {
+                        (node.offset as usize, index + 1)
+                    } else {
+                        (index + 1, node.offset as usize)
+                    };
+                    stack[top] = far;
+                    top += 1;
+                    index = near;
+                    continue;
+                }
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            index = stack[top];
+        }
+        is_hit
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.nodes.first().map(|n| n.bbox)
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        let b = self.nodes[0].bbox;
+        a.call((b.maximum + b.minimum) / 2.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
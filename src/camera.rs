@@ -1,6 +1,128 @@
 use crate::ray::{Point3, Ray};
 use crate::vec3::Vec3;
 use crate::common::degrees_to_radians;
+use crate::sampler::{sample_1d, sample_2d};
+use crate::hit::HitRecorder;
+use crate::sences::SencesManger;
+use std::f64::consts::PI;
+
+//物理相机的镜头参数 焦距 传感器的长度单位是毫米
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Lens{
+    pub(crate) focal_length:f64,
+    //光圈 f 值 通光孔径 = 焦距 / f 值
+    pub(crate) f_stop:f64,
+    //传感器宽度 全画幅是 36mm 高度按画面比例算
+    pub(crate) sensor_width:f64,
+    //对焦距离(场景单位) 为 None 时自动对焦到画面中心的物体上
+    pub(crate) focus_distance:Option<f64>,
+    //光圈叶片数 少于 3 片时光圈是圆的 焦外光斑是圆形 否则是正多边形
+    pub(crate) blades:u32,
+    //叶片整体旋转的角度
+    pub(crate) blade_rotation:f64,
+    //一个场景单位是多少毫米 默认场景单位是米
+    pub(crate) mm_per_unit:f64,
+}
+
+impl Lens{
+    pub(crate) fn new() -> Self{
+        Self{
+            focal_length: 50.0,
+            f_stop: 2.8,
+            sensor_width: 36.0,
+            focus_distance: None,
+            blades: 0,
+            blade_rotation: 0.0,
+            mm_per_unit: 1000.0,
+        }
+    }
+
+    //竖直方向的视角(度)
+    pub(crate) fn vfov(&self, aspect_ratio:f64) -> f64{
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    //通光孔径的直径(场景单位)
+    pub(crate) fn aperture(&self) -> f64{
+        self.focal_length / self.f_stop / self.mm_per_unit
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum FisheyeMapping{
+    //离画面中心的距离和视线夹角成正比
+    Equidistant,
+    //等立体角 画面上的面积和立体角成正比
+    Equisolid,
+}
+
+//画面坐标到射线的投影方式
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Projection{
+    //透视 可以有景深
+    Perspective,
+    //平行投影 参数为画面的高度(场景单位) 射线都沿视线方向 用来出建筑立面图
+    Orthographic(f64),
+    //圆形鱼眼 参数为圆的视角(度) 圆外面没有射线
+    Fisheye(FisheyeMapping, f64),
+    //360 度全景 横向是经度 纵向是纬度 画面宽高比应为 2:1
+    Equirectangular,
+}
+
+//多个视图拼到一张图里的方式
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum ViewLayout{
+    //从左到右排成一行
+    SideBySide,
+    //从上到下排成一列
+    OverUnder,
+}
+
+impl ViewLayout{
+    //n 个视图拼成的 (列数, 行数)
+    pub(crate) fn grid(&self, n:usize) -> (usize, usize){
+        match self {
+            ViewLayout::SideBySide => { (n, 1) }
+            ViewLayout::OverUnder => { (1, n) }
+        }
+    }
+}
+
+//双目相机 左右眼沿相机的 u 方向各偏开瞳距的一半 视线保持平行
+//画面往中间平移 让两个视锥在会聚距离上重合 不像两眼往里转那样产生上下视差
+//360 度全景用全向立体(ODS): 眼睛不固定 每条射线的起点沿它所在经度的右手方向偏开 转头看哪个方向都有正确的视差
+//其他投影不支持
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct StereoRig{
+    //瞳距(场景单位)
+    pub(crate) interocular:f64,
+    //会聚距离 这个距离上的物体左右眼没有视差 为 0 时会聚在无穷远
+    pub(crate) convergence:f64,
+}
+
+impl StereoRig{
+    //由中间的相机得到 [左眼, 右眼] 要先设置好相机的投影
+    pub(crate) fn eyes(&self, center:&Camera) -> [Camera;2]{
+        let half = self.interocular / 2.0;
+        let mut left = *center;
+        let mut right = *center;
+        match center.projection {
+            Projection::Perspective => {
+                left.shift_eye(-half, self.convergence);
+                right.shift_eye(half, self.convergence);
+            }
+            Projection::Equirectangular => {
+                left.eye_offset = -half;
+                right.eye_offset = half;
+                left.convergence = self.convergence;
+                right.convergence = self.convergence;
+            }
+            _ => { panic!("双目相机只支持透视和 360 度全景投影!") }
+        }
+        [left, right]
+    }
+}
 
 #[derive(Copy, Clone,Debug)]
 pub(crate) struct Camera {
@@ -8,8 +130,21 @@ pub(crate) struct Camera {
     lower_left_corner:Point3,
     horizontal:Vec3,
     vertical:Vec3,
+    //对焦距离为 1 时的画面大小
+    viewport_width:f64,
+    viewport_height:f64,
+    focus_dist:f64,
     lens_radius:f64,
+    blades:u32,
+    blade_rotation:f64,
     u:Vec3, v:Vec3, w:Vec3,
+    projection:Projection,
+    //全向立体的眼睛偏移 负数是左眼 和会聚距离 只有 360 度全景用
+    eye_offset:f64,
+    convergence:f64,
+    //快门打开和关闭的时刻
+    time0:f64,
+    time1:f64,
 }
 
 impl Camera{
@@ -31,27 +166,186 @@ impl Camera{
         let u = Vec3::unit_vector(Vec3::cross(vup,w));
         let v = Vec3::cross(w,u);
 
-        let origin = lookfrom;
-        let horizontal = u * (viewport_width * focus_dist);
-        let vertical = v * (viewport_height * focus_dist);
-        let lower_left_corner = origin - horizontal / 2.0 -
-            vertical / 2.0 - w * focus_dist;
-        Self{
-            origin,
-            lower_left_corner,
-            horizontal,
-            vertical,
+        let mut camera = Self{
+            origin: lookfrom,
+            lower_left_corner: lookfrom,
+            horizontal: u,
+            vertical: v,
+            viewport_width,
+            viewport_height,
+            focus_dist,
             lens_radius: aperture / 2.0,
+            blades: 0,
+            blade_rotation: 0.0,
             u,
             v,
-            w
+            w,
+            projection: Projection::Perspective,
+            eye_offset: 0.0,
+            convergence: 0.0,
+            time0: 0.0,
+            time1: 0.0,
+        };
+        camera.set_focus_distance(focus_dist);
+        camera
+    }
+
+    //按镜头参数算出视角和光圈 没给对焦距离时先对焦到 lookat 再由 autofocus 调整
+    pub(crate) fn form_lens(lookfrom:Point3, lookat:Point3, vup:Vec3, aspect_ratio:f64, lens:&Lens) -> Self{
+        let focus_dist = lens.focus_distance.unwrap_or((lookfrom - lookat).length());
+        let mut camera = Self::new(lookfrom, lookat, vup, lens.vfov(aspect_ratio), aspect_ratio, lens.aperture(), focus_dist);
+        camera.blades = lens.blades;
+        camera.blade_rotation = degrees_to_radians(lens.blade_rotation);
+        camera
+    }
+
+    //焦平面放在离镜头 focus_dist 的地方 焦平面上的点不模糊
+    pub(crate) fn set_focus_distance(&mut self, focus_dist:f64){
+        self.focus_dist = focus_dist;
+        self.horizontal = self.u * (self.viewport_width * focus_dist);
+        self.vertical = self.v * (self.viewport_height * focus_dist);
+        self.lower_left_corner = self.origin - self.horizontal / 2.0 -
+            self.vertical / 2.0 - self.w * focus_dist;
+    }
+
+    //从镜头中心向画面中心发一条射线 对焦到打中的物体上 什么都没打中就保持原来的对焦距离
+    pub(crate) fn autofocus(&mut self, world:&SencesManger) -> Option<f64>{
+        let mut rec = HitRecorder::new();
+        let ray = Ray::form_time(self.origin, -self.w, self.time0);
+        if !world.hit(ray, 0.0, f64::MAX, &mut rec) {
+            return None;
+        }
+        let dist = Vec3::dot(rec.p.unwrap() - self.origin, -self.w);
+        self.set_focus_distance(dist);
+        Some(dist)
+    }
+
+    //镜头沿 u 平移 offset 焦平面上的画面跟着平移 offset * (1 - 对焦距离 / 会聚距离)
+    //这样平移后的视锥和原来的视锥在会聚距离上截出同一个矩形
+    fn shift_eye(&mut self, offset:f64, convergence:f64){
+        let shift = if convergence > 0.0 { 1.0 - self.focus_dist / convergence } else { 1.0 };
+        self.origin += self.u * offset;
+        self.lower_left_corner += self.u * (offset * shift);
+    }
+
+    //透视以外的投影都是针孔 不模拟镜头和景深
+    pub(crate) fn set_projection(&mut self, projection:Projection){
+        self.projection = projection;
+    }
+
+    //默认快门瞬间开合 没有运动模糊
+    pub(crate) fn set_shutter(&mut self,time0:f64,time1:f64){
+        self.time0 = time0;
+        self.time1 = time1;
+    }
+
+    //在光圈上均匀取一点 圆形光圈或者正多边形光圈
+    fn sample_aperture(&self) -> Vec3{
+        if self.blades < 3 {
+            return Vec3::random_in_unit_disk();
+        }
+        //每个叶片对应一个等面积的三角形 先选三角形 再在三角形里均匀取点
+        let n = self.blades as f64;
+        let k = (sample_1d() * n).floor().min(n - 1.0);
+        let a0 = self.blade_rotation + 2.0 * PI * k / n;
+        let a1 = a0 + 2.0 * PI / n;
+        let (mut s, mut t) = sample_2d();
+        if s + t > 1.0 {
+            s = 1.0 - s;
+            t = 1.0 - t;
         }
+        Vec3::form(a0.cos() * s + a1.cos() * t, a0.sin() * s + a1.sin() * t, 0.0)
     }
-    pub(crate) fn get_ray(&self,u:f64,v:f64) -> Ray{
-        let rd =  Vec3::random_in_unit_disk() * self.lens_radius ;
-        let offset = u * rd.x + v * rd.y;
-        return Ray::form(self.origin + offset,self.lower_left_corner +
-              self.horizontal * u + self.vertical * v - self.origin - offset)
+
+    //u v 是画面上的坐标 [0,1] 鱼眼圆外面的点没有对应的射线
+    pub(crate) fn get_ray(&self,u:f64,v:f64) -> Option<Ray>{
+        let time = if self.time1 > self.time0 { self.time0 + (self.time1 - self.time0) * sample_1d() } else { self.time0 };
+        let aspect_ratio = self.viewport_width / self.viewport_height;
+        match self.projection {
+            Projection::Perspective => {
+                let rd = self.sample_aperture() * self.lens_radius;
+                let offset = self.u * rd.x + self.v * rd.y;
+                Some(Ray::form_time(self.origin + offset,self.lower_left_corner +
+                      self.horizontal * u + self.vertical * v - self.origin - offset, time))
+            }
+            Projection::Orthographic(height) => {
+                let origin = self.origin + self.u * ((u - 0.5) * height * aspect_ratio) + self.v * ((v - 0.5) * height);
+                Some(Ray::form_time(origin, -self.w, time))
+            }
+            Projection::Fisheye(mapping, fov) => {
+                //圆的直径等于画面高度
+                let x = (u - 0.5) * 2.0 * aspect_ratio;
+                let y = (v - 0.5) * 2.0;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half = degrees_to_radians(fov) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => { r * half }
+                    FisheyeMapping::Equisolid => { 2.0 * (r * (half / 2.0).sin()).asin() }
+                };
+                let phi = y.atan2(x);
+                let dir = (self.u * phi.cos() + self.v * phi.sin()) * theta.sin() - self.w * theta.cos();
+                Some(Ray::form_time(self.origin, dir, time))
+            }
+            Projection::Equirectangular => {
+                //画面中心对着视线方向
+                let phi = (u - 0.5) * 2.0 * PI;
+                let theta = (v - 0.5) * PI;
+                let dir = self.u * (theta.cos() * phi.sin()) + self.v * theta.sin() - self.w * (theta.cos() * phi.cos());
+                //全向立体: 起点沿这个经度视线的右手方向偏开 有会聚距离时射线往里转 在会聚距离上和中间的视线相交
+                let offset = (self.u * phi.cos() + self.w * phi.sin()) * self.eye_offset;
+                let dir = if self.convergence > 0.0 { dir * self.convergence - offset } else { dir };
+                Some(Ray::form_time(self.origin + offset, dir, time))
+            }
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panorama() -> Camera{
+        let mut camera = Camera::new(Point3::form(0.0, 0.0, 0.0), Point3::form(0.0, 0.0, -1.0), Vec3::form(0.0, 1.0, 0.0), 90.0, 2.0, 0.0, 1.0);
+        camera.set_projection(Projection::Equirectangular);
+        camera
+    }
+
+    #[test]
+    fn ods_eyes_sit_on_the_view_circle(){
+        let rig = StereoRig{ interocular: 0.2, convergence: 0.0 };
+        let [left, right] = rig.eyes(&panorama());
+        for &u in &[0.1, 0.3, 0.5, 0.8] {
+            let l = left.get_ray(u, 0.5).unwrap();
+            let r = right.get_ray(u, 0.5).unwrap();
+            //两眼的连线和视线垂直 长度是瞳距 右眼在视线的右手边
+            let baseline = r.origin() - l.origin();
+            let dir = Vec3::unit_vector(l.direction());
+            assert!((baseline.length() - 0.2).abs() < 1e-12);
+            assert!(Vec3::dot(baseline, dir).abs() < 1e-12);
+            assert!(Vec3::dot(Vec3::cross(dir, baseline), Vec3::form(0.0, 1.0, 0.0)) < 0.0);
+        }
+    }
+
+    #[test]
+    fn ods_rays_meet_at_convergence(){
+        let rig = StereoRig{ interocular: 0.2, convergence: 4.0 };
+        let [left, right] = rig.eyes(&panorama());
+        let l = left.get_ray(0.3, 0.5).unwrap();
+        let r = right.get_ray(0.3, 0.5).unwrap();
+        //不归一化的方向正好指到会聚距离上
+        let target_l = l.origin() + l.direction();
+        let target_r = r.origin() + r.direction();
+        assert!((target_l - target_r).length() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic]
+    fn stereo_rejects_fisheye(){
+        let mut camera = panorama();
+        camera.set_projection(Projection::Fisheye(FisheyeMapping::Equidistant, 180.0));
+        StereoRig{ interocular: 0.2, convergence: 0.0 }.eyes(&camera);
+    }
+}
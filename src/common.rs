@@ -180,6 +180,18 @@ impl Perlin{
         p
     }
 
+    //分形布朗运动 每层频率乘以 lacunarity 振幅乘以 gain
+    pub(crate) fn fbm(&self, mut p:Point3, octaves:i32, lacunarity:f64, gain:f64) -> f64{
+        let mut accum = 0.0;
+        let mut weight = 1.0;
+        for _i in 0 .. octaves {
+            accum += weight * self.noise(&p);
+            weight *= gain;
+            p *= lacunarity;
+        }
+        accum
+    }
+
     pub(crate) fn turb(&self, mut p:Point3, depth:i32) -> f64{
         let mut accum = 0.0;
         let mut weight = 1.0;
@@ -203,6 +215,57 @@ impl Perlin{
 
 
 
+//细胞噪声 每个整数格子里放一个随机特征点 返回到最近(F1)和次近(F2)特征点的距离
+pub(crate) struct Worley{
+    points:Vec<Vec3>,
+    perm_x:Vec<i32>,
+    perm_y:Vec<i32>,
+    perm_z:Vec<i32>,
+}
+
+impl Worley{
+    pub(crate) fn new() -> Self{
+        let mut points = vec![];
+        for _i in 0 .. 256{
+            points.push(Vec3::random());
+        }
+        Worley{
+            points,
+            perm_x: Perlin::perlin_generate_perm(),
+            perm_y: Perlin::perlin_generate_perm(),
+            perm_z: Perlin::perlin_generate_perm()
+        }
+    }
+
+    fn feature_point(&self, i:i32, j:i32, k:i32) -> Point3{
+        let index = self.perm_x[(i & 255) as usize] ^ self.perm_y[(j & 255) as usize] ^ self.perm_z[(k & 255) as usize];
+        point3!(i, j, k) + self.points[index as usize]
+    }
+
+    pub(crate) fn noise(&self, p:&Point3) -> (f64, f64){
+        let i = p.x.floor() as i32;
+        let j = p.y.floor() as i32;
+        let k = p.z.floor() as i32;
+        let mut f1 = f64::MAX;
+        let mut f2 = f64::MAX;
+        //特征点只会落在相邻的 3x3x3 个格子里
+        for di in -1 .. 2 {
+            for dj in -1 .. 2 {
+                for dk in -1 .. 2 {
+                    let d = (self.feature_point(i + di, j + dj, k + dk) - *p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
 pub(crate) fn except(n:i32, p:f64) -> f64{
     n as f64 * (1.0 - p) * p.powi(n-1)
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::hit::{Hittable, HitRecorder};
+use crate::shape::AABB;
+use crate::common::{surrounding_box, cmp_f64, Axis};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+//一个子物体最多记录的交点数 防止不封闭的物体死循环
+const MAX_CSG_HITS:usize = 64;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum CsgOp{
+    Union,
+    Intersection,
+    //左边减去右边
+    Difference,
+}
+
+impl CsgOp{
+    fn inside(&self, in_left:bool, in_right:bool) -> bool{
+        match self {
+            CsgOp::Union => { in_left || in_right }
+            CsgOp::Intersection => { in_left && in_right }
+            CsgOp::Difference => { in_left && !in_right }
+        }
+    }
+}
+
+//两个封闭物体的布尔运算 沿射线记录进出每个物体的区间再合并
+pub(crate) struct Csg{
+    left:Option<Arc<dyn Hittable>>,
+    right:Option<Arc<dyn Hittable>>,
+    op:CsgOp,
+}
+
+impl Csg{
+    pub(crate) fn form(left:Arc<dyn Hittable>, right:Arc<dyn Hittable>, op:CsgOp) -> Self{
+        Self{
+            left: Some(left),
+            right: Some(right),
+            op,
+        }
+    }
+
+    pub(crate) fn union(left:Arc<dyn Hittable>, right:Arc<dyn Hittable>) -> Self{
+        Self::form(left, right, CsgOp::Union)
+    }
+
+    pub(crate) fn intersection(left:Arc<dyn Hittable>, right:Arc<dyn Hittable>) -> Self{
+        Self::form(left, right, CsgOp::Intersection)
+    }
+
+    pub(crate) fn difference(left:Arc<dyn Hittable>, right:Arc<dyn Hittable>) -> Self{
+        Self::form(left, right, CsgOp::Difference)
+    }
+}
+
+//从 t_min 开始依次找出射线和物体的所有交点 超过 t_max 之后再多找一个就够了
+//返回射线在 t_min 处是否在物体内部 和按 t 排好序的交点
+//找下一个交点时从上一个交点按误差范围推过表面重新发射线 不用固定的距离 薄壁和大场景都不会出错
+fn collect_hits(obj:&dyn Hittable, ray:Ray, t_min:f64, t_max:f64) -> (bool, Vec<HitRecorder>){
+    let mut hits:Vec<HitRecorder> = vec![];
+    let mut next = ray;
+    let mut next_t_min = t_min;
+    //next 的起点在原射线上对应的 t
+    let mut base_t = 0.0;
+    let dir_len2 = ray.direction().length_squared();
+    while hits.len() < MAX_CSG_HITS {
+        let mut rec = HitRecorder::new();
+        if !obj.hit(next, next_t_min, f64::MAX, &mut rec) {
+            break;
+        }
+        next = rec.spawn_ray(&next, ray.direction());
+        next_t_min = 0.0;
+        rec.t += base_t;
+        base_t = Vec3::dot(next.origin() - ray.origin(), ray.direction()) / dir_len2;
+        let past_end = rec.t > t_max;
+        hits.push(rec);
+        if past_end {
+            break;
+        }
+    }
+    //第一个交点是从里面出去 说明起点在物体内部
+    let inside = match hits.first() {
+        None => { false }
+        Some(rec) => { !rec.front_face }
+    };
+    (inside, hits)
+}
+
+impl Debug for Csg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Csg({:?}, {:?}, {:?})",self.op,self.left,self.right)
+    }
+}
+
+impl Hittable for Csg{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let (mut in_left, left_hits) = collect_hits(self.left.as_ref().unwrap().as_ref(), ray, t_min, t_max);
+        let (mut in_right, right_hits) = collect_hits(self.right.as_ref().unwrap().as_ref(), ray, t_min, t_max);
+        let mut events:Vec<(HitRecorder, bool)> = left_hits.into_iter().map(|r| (r, true))
+            .chain(right_hits.into_iter().map(|r| (r, false))).collect();
+        events.sort_by(|a, b| cmp_f64(a.0.t, b.0.t));
+
+        let inside = self.op.inside(in_left, in_right);
+        for (hit, from_left) in events{
+            if hit.t > t_max {
+                break;
+            }
+            if from_left {
+                in_left = hit.front_face;
+            } else {
+                in_right = hit.front_face;
+            }
+            let now_inside = self.op.inside(in_left, in_right);
+            if now_inside == inside {
+                continue;
+            }
+            //法线始终朝着射线来的方向 只需要按组合后的实体重新判断是进入还是离开
+            //这样差集里被挖掉的部分 折射时内外也是对的
+            *rec = hit;
+            rec.front_face = now_inside;
+            return true;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let left = self.left.as_ref().unwrap().bounding_box()?;
+        match self.op {
+            CsgOp::Difference => { Some(left) }
+            CsgOp::Union => {
+                let right = self.right.as_ref().unwrap().bounding_box()?;
+                surrounding_box(left, right)
+            }
+            CsgOp::Intersection => {
+                let right = self.right.as_ref().unwrap().bounding_box()?;
+                let min = Vec3::max(left.minimum, right.minimum);
+                let max = Vec3::min(left.maximum, right.maximum);
+                //两个包围盒不相交时交集为空 给一个退化的盒子
+                Some(AABB::form(min, Vec3::max(min, max)))
+            }
+        }
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        match self.bounding_box() {
+            None => { self.left.as_ref().unwrap().get_center_point(a) }
+            Some(b) => { a.call((b.minimum + b.maximum) / 2.0) }
+        }
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
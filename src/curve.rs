@@ -0,0 +1,197 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::hit::{Hittable, HitRecorder};
+use crate::shape::AABB;
+use crate::common::{f64_near_zero, Axis};
+use crate::material::Materials;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+//递归细分的最大层数
+const MAX_CURVE_DEPTH:u32 = 10;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum CurveType{
+    //总是朝着射线的扁平带子 适合很细的头发和草
+    Flat,
+    //圆柱截面 法线绕发丝一圈变化
+    Cylinder,
+}
+
+//三次 Bezier 曲线 宽度从 width0 线性变化到 width1
+pub(crate) struct Curve{
+    cp:[Point3;4],
+    width0:f64,
+    width1:f64,
+    kind:CurveType,
+    depth:u32,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+//递归时的一小段曲线 [u0, u1] 是它在整条曲线上的参数范围
+#[derive(Copy, Clone)]
+struct Segment{
+    cp:[Point3;4],
+    u0:f64,
+    u1:f64,
+    depth:u32,
+}
+
+//de Casteljau 在中点把曲线分成两段
+fn split_bezier(cp:[Point3;4]) -> ([Point3;4], [Point3;4]){
+    let p01 = (cp[0] + cp[1]) / 2.0;
+    let p12 = (cp[1] + cp[2]) / 2.0;
+    let p23 = (cp[2] + cp[3]) / 2.0;
+    let p012 = (p01 + p12) / 2.0;
+    let p123 = (p12 + p23) / 2.0;
+    let mid = (p012 + p123) / 2.0;
+    ([cp[0], p01, p012, mid], [mid, p123, p23, cp[3]])
+}
+
+fn bezier_derivative(cp:[Point3;4], u:f64) -> Vec3{
+    let a = cp[1] - cp[0];
+    let b = cp[2] - cp[1];
+    let c = cp[3] - cp[2];
+    (a * ((1.0 - u) * (1.0 - u)) + b * (2.0 * u * (1.0 - u)) + c * (u * u)) * 3.0
+}
+
+fn control_box(cp:&[Point3;4], pad:f64) -> AABB{
+    let mut min = cp[0];
+    let mut max = cp[0];
+    for p in cp.iter(){
+        min = Vec3::min(min, *p);
+        max = Vec3::max(max, *p);
+    }
+    AABB::form(min - pad, max + pad)
+}
+
+impl Curve{
+    pub(crate) fn form(cp:[Point3;4], width0:f64, width1:f64, kind:CurveType, ma:Arc<dyn Materials>) -> Self{
+        //按控制点的弯曲程度决定细分层数 让每一段和直线的误差小于宽度的 1/20
+        let mut l0:f64 = 0.0;
+        for i in 0..2{
+            l0 = l0.max((cp[i] - cp[i + 1] * 2.0 + cp[i + 2]).length());
+        }
+        let eps = f64::max(width0, width1) * 0.05;
+        let depth = if l0 <= 0.0 || eps <= 0.0 {
+            0
+        } else {
+            ((std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0).round().clamp(0.0, MAX_CURVE_DEPTH as f64) as u32
+        };
+        Self{
+            cp,
+            width0,
+            width1,
+            kind,
+            depth,
+            mp: Some(ma),
+        }
+    }
+
+    fn width(&self, u:f64) -> f64{
+        self.width0 + (self.width1 - self.width0) * u
+    }
+
+    //不断二分 用控制点包围盒剔除 到最底层把这一段当作直线求交
+    fn hit_segment(&self, ray:Ray, seg:Segment, t_min:f64, closest:&mut f64, rec:&mut HitRecorder) -> bool{
+        let Segment{ cp, u0, u1, depth } = seg;
+        let pad = f64::max(self.width(u0), self.width(u1)) / 2.0;
+        if !control_box(&cp, pad).hit(ray, t_min, *closest, rec) {
+            return false;
+        }
+        if depth > 0 {
+            let (left, right) = split_bezier(cp);
+            let um = (u0 + u1) / 2.0;
+            let hit_left = self.hit_segment(ray, Segment{ cp: left, u0, u1: um, depth: depth - 1 }, t_min, closest, rec);
+            let hit_right = self.hit_segment(ray, Segment{ cp: right, u0: um, u1, depth: depth - 1 }, t_min, closest, rec);
+            return hit_left || hit_right;
+        }
+
+        let dir_len = ray.direction().length();
+        let d = ray.direction() / dir_len;
+        let o = ray.origin();
+        let axis = cp[3] - cp[0];
+        let seg_len = axis.length();
+        if f64_near_zero(seg_len) {
+            return false;
+        }
+        let a = axis / seg_len;
+        //射线和线段所在直线的最近点 t 按单位方向计算 s 是线段上的长度
+        let w = o - cp[0];
+        let b = Vec3::dot(d, a);
+        let denom = 1.0 - b * b;
+        if f64_near_zero(denom) {
+            return false;
+        }
+        let dw = Vec3::dot(d, w);
+        let aw = Vec3::dot(a, w);
+        let mut t = (b * aw - dw) / denom;
+        let mut s = (aw - b * dw) / denom;
+        if s < 0.0 || s > seg_len {
+            return false;
+        }
+        let u = u0 + (u1 - u0) * s / seg_len;
+        let radius = self.width(u) / 2.0;
+        let offset = (o + d * t) - (cp[0] + a * s);
+        if offset.length_squared() > radius * radius {
+            return false;
+        }
+        let side = Vec3::cross(a, d).unit_vector();
+        let h = Vec3::dot(offset, side) / radius;
+        let normal = match self.kind {
+            CurveType::Flat => {
+                -(d - a * b).unit_vector()
+            }
+            CurveType::Cylinder => {
+                //从最近点往回退到圆柱表面
+                let back = (radius * radius - offset.length_squared()).sqrt() / denom.sqrt();
+                t -= back;
+                s = Vec3::dot(o + d * t - cp[0], a);
+                let p = o + d * t;
+                (p - (cp[0] + a * s)).unit_vector()
+            }
+        };
+        let t = t / dir_len;
+        if t < t_min || t > *closest {
+            return false;
+        }
+        *closest = t;
+        rec.t = t;
+        rec.p = Some(ray.at(t));
+        rec.u = u;
+        rec.v = (h.clamp(-1.0, 1.0) + 1.0) / 2.0;
+        rec.material = self.mp.clone();
+        rec.set_face_normal(ray, normal);
+        rec.set_tangents(bezier_derivative(self.cp, u), side * (radius * 2.0));
+        true
+    }
+}
+
+impl Debug for Curve {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Curve({:?}, {:?})",self.cp,self.kind)
+    }
+}
+
+impl Hittable for Curve{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let mut closest = t_max;
+        self.hit_segment(ray, Segment{ cp: self.cp, u0: 0.0, u1: 1.0, depth: self.depth }, t_min, &mut closest, rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(control_box(&self.cp, f64::max(self.width0, self.width1) / 2.0))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call((self.cp[0] + self.cp[1] + self.cp[2] + self.cp[3]) / 4.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
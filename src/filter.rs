@@ -0,0 +1,154 @@
+use std::f64::consts::PI;
+
+//像素重建滤波器 采样点按到像素中心的距离加权 半径超过半个像素时会溅射到相邻像素
+pub(crate) trait Filter: Send + Sync{
+    //滤波器在 x y 方向的半径(像素)
+    fn radius(&self) -> f64;
+    //(x, y) 是采样点相对像素中心的偏移 权重可以是负的
+    fn evaluate(&self, x:f64, y:f64) -> f64;
+}
+
+//盒子滤波 半径 0.5 时每个采样只落在自己的像素里 等于直接求平均
+pub(crate) struct BoxFilter{
+    radius:f64,
+}
+
+impl BoxFilter{
+    pub(crate) fn form(radius:f64) -> Self{
+        Self{ radius }
+    }
+}
+
+impl Filter for BoxFilter{
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+//三角形滤波 权重随距离线性减小
+pub(crate) struct TentFilter{
+    radius:f64,
+}
+
+impl TentFilter{
+    pub(crate) fn form(radius:f64) -> Self{
+        Self{ radius }
+    }
+}
+
+impl Filter for TentFilter{
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+//高斯滤波 减去半径处的值 让权重在边界上降到 0
+pub(crate) struct GaussianFilter{
+    radius:f64,
+    sigma:f64,
+    edge:f64,
+}
+
+impl GaussianFilter{
+    pub(crate) fn form(radius:f64, sigma:f64) -> Self{
+        Self{
+            radius,
+            sigma,
+            edge: Self::gaussian(radius, sigma),
+        }
+    }
+
+    fn gaussian(x:f64, sigma:f64) -> f64{
+        (-x * x / (2.0 * sigma * sigma)).exp()
+    }
+
+    fn evaluate_1d(&self, x:f64) -> f64{
+        (Self::gaussian(x, self.sigma) - self.edge).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter{
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+//Mitchell-Netravali 三次滤波 b = c = 1/3 时模糊和振铃比较平衡 边缘附近有负权重
+pub(crate) struct MitchellFilter{
+    radius:f64,
+    b:f64,
+    c:f64,
+}
+
+impl MitchellFilter{
+    pub(crate) fn form(radius:f64, b:f64, c:f64) -> Self{
+        Self{ radius, b, c }
+    }
+
+    //x 缩放到 [-2, 2]
+    fn evaluate_1d(&self, x:f64) -> f64{
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x +
+                (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x +
+                (6.0 - 2.0 * b)) / 6.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter{
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+//Blackman-Harris 窗 比高斯更锐利 旁瓣很低
+pub(crate) struct BlackmanHarrisFilter{
+    radius:f64,
+}
+
+impl BlackmanHarrisFilter{
+    pub(crate) fn form(radius:f64) -> Self{
+        Self{ radius }
+    }
+
+    fn evaluate_1d(&self, x:f64) -> f64{
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+        //把 [-radius, radius] 映射到窗口的 [0, 1]
+        let n = x / (2.0 * self.radius) + 0.5;
+        0.35875 - 0.48829 * (2.0 * PI * n).cos() + 0.14128 * (4.0 * PI * n).cos() - 0.01168 * (6.0 * PI * n).cos()
+    }
+}
+
+impl Filter for BlackmanHarrisFilter{
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
@@ -5,12 +5,22 @@ use std::sync::Arc;
 use crate::material::Materials;
 use crate::shape::AABB;
 use crate::common::Axis;
+use crate::precision::{offset_ray_origin, ray_point_error};
 
 
 #[derive( Clone)]
 pub(crate) struct HitRecorder {
     pub(crate) p:Option<Point3>,
+    //击中点每个分量的浮点误差上界 为 None 时发射新射线按射线求交的一般误差估计
+    pub(crate) p_error:Option<Vec3>,
     pub(crate) normal:Option<Vec3>,
+    //几何法线 和 normal 一样朝着射线来的一侧 插值法线 法线贴图只改 normal 不改它
+    pub(crate) geometric_normal:Option<Vec3>,
+    //表面对 u v 的偏导数 dp/du dp/dv 法线贴图和凹凸贴图用
+    pub(crate) tangent:Option<Vec3>,
+    pub(crate) bitangent:Option<Vec3>,
+    //材质在 scatter 里算出的这次采样的衰减 衰减和采样到的方向有关的材质(比如头发)才用
+    pub(crate) attenuation:Option<Vec3>,
     pub(crate) material:Option<Arc<dyn Materials>>,
     pub(crate) t:f64,
     pub(crate) u:f64,
@@ -25,6 +35,10 @@ pub(crate) trait Hittable:Send+ Sync +Debug  {
     fn get_center_point(&self, a:&Axis) -> f64;
     fn pdf_value(&self,rec:&mut HitRecorder,p:Point3,dir:Vec3) -> f64;
     fn random_sample(&self) -> Vec3;
+    //物体是单个三角形时返回三个顶点 宽 BVH 把它们打包成 4 个一组一起求交
+    fn triangle(&self) -> Option<[Point3;3]> {
+        None
+    }
 }
 
 //计算射线物体的前面还是后面
@@ -32,7 +46,12 @@ impl HitRecorder{
     pub(crate) fn new() -> HitRecorder {
         Self{
             p: None,
+            p_error: None,
             normal: None,
+            geometric_normal: None,
+            tangent: None,
+            bitangent: None,
+            attenuation: None,
             material: None,
             t: 0.0,
             u: 0.0,
@@ -41,7 +60,9 @@ impl HitRecorder{
             is_specular: false
         }
     }
+    //顺带清掉上一个形状留下的 p_error 知道自己误差上界的形状在调用之后再写
     pub(crate) fn set_face_normal(&mut self, ray:Ray, outward_normal:Vec3){
+        self.p_error = None;
         let front_face = Vec3::dot(ray.direction(),outward_normal) < 0.0;
         if front_face {
             self.normal = Some(outward_normal);
@@ -50,5 +71,22 @@ impl HitRecorder{
             self.normal = Some(- outward_normal);
             self.front_face = false;
         }
+        self.geometric_normal = self.normal;
+    }
+
+    //从击中点发出新的射线 起点沿几何法线推到误差范围之外 推向出射方向那一侧
+    //偏移量跟着坐标的大小走 场景放大缩小都不会打中自己 也不会穿过薄的物体漏光
+    pub(crate) fn spawn_ray(&self, ray_in:&Ray, direction:Vec3) -> Ray{
+        let p = self.p.unwrap();
+        let p_error = match self.p_error {
+            Some(e) => { e }
+            None => { ray_point_error(ray_in, self.t) }
+        };
+        let n = self.geometric_normal.or(self.normal).unwrap();
+        Ray::form_time(offset_ray_origin(p, p_error, n, direction), direction, ray_in.time())
     }
-}
\ No newline at end of file
+    pub(crate) fn set_tangents(&mut self, dpdu:Vec3, dpdv:Vec3){
+        self.tangent = Some(dpdu);
+        self.bitangent = Some(dpdv);
+    }
+}
@@ -9,12 +9,24 @@ mod common;
 mod material;
 mod stl_reader;
 mod bvh;
-mod sort;
 mod texture;
 mod sences;
 mod macros;
 mod pdf;
 mod windows;
+mod normal_map;
+mod obj_reader;
+mod mesh;
+mod transform;
+mod csg;
+mod sdf;
+mod curve;
+mod tlas;
+mod simd;
+mod wide_bvh;
+mod precision;
+mod filter;
+mod sampler;
 
 use std::fmt::{Display, Formatter};
 use crate::vec3::Vec3;
@@ -24,10 +36,12 @@ use std::borrow::{BorrowMut, Borrow};
 use crate::ray::{Point3, Ray};
 use crate::hit::{HitRecorder};
 use std::sync::{Arc, mpsc, Mutex, RwLock};
-use crate::camera::Camera;
-use crate::common::{rand_f64, clamp, rand_range_f64};
+use crate::camera::{Camera, Lens, Projection, FisheyeMapping, StereoRig, ViewLayout};
+use crate::common::{clamp, degrees_to_radians, rand_i3_range};
+use crate::sampler::{Sampler, RandomSampler, SobolSampler, HaltonSampler, BlueNoiseSampler, blue_noise_mask, set_sampler, start_pixel_sample, sample_1d, sample_2d};
+use crate::filter::{Filter, BoxFilter, TentFilter, GaussianFilter, MitchellFilter, BlackmanHarrisFilter};
 use std::time::Instant;
-use crate::sences::{two_spheres, random_scene, two_perlin_spheres, simple_light, cornell_box, SencesManger};
+use crate::sences::{two_spheres, random_scene, two_perlin_spheres, simple_light, cornell_box, procedural_textures, obj_scene, instanced_cats, motion_blur, primitives, csg_scene, sdf_scene, subdivided_cats, furry_cat, bokeh, SencesManger};
 use std::f64::consts::PI;
 use std::fs::File;
 use std::io::Write;
@@ -58,8 +72,10 @@ impl Color{
 
 fn ray_color(ray:Ray,background:&Color,sences_manager:Arc<SencesManger>,depth:i32) -> Color{
     let mut rec = HitRecorder::new();
-    if sences_manager.hit(ray, 0.0001, f64::MAX, rec.borrow_mut()){
-        let ray = rec.material.clone().unwrap().scatter(&ray, &mut rec);
+    //新射线的起点已经推出了击中点的误差范围 不需要再用 t_min 挡住自己
+    if sences_manager.hit(ray, 0.0, f64::MAX, rec.borrow_mut()){
+        let ray_in = ray;
+        let ray = rec.material.clone().unwrap().scatter(&ray_in, &mut rec);
         let emitted= rec.material.clone().unwrap().clone().emitted(rec.u,rec.v,rec.p.unwrap());
         //蒙特卡洛积分
         let pdf =  0.5 / PI;
@@ -73,10 +89,11 @@ fn ray_color(ray:Ray,background:&Color,sences_manager:Arc<SencesManger>,depth:i3
                     None => {}
                     Some(light) => {
                         let light_p = light.random_sample();
-                        let to_light = light_p - rec.p.unwrap();
-                        let ray_light = Ray::form(rec.p.unwrap(),to_light);
+                        let ray_light = rec.spawn_ray(&ray_in, light_p - rec.p.unwrap());
+                        //pdf 从推出误差范围的起点算 求交不需要 t_min
+                        let to_light = light_p - ray_light.origin();
                         let mut light_rec = HitRecorder::new();
-                        let pdf_light = light.pdf_value(light_rec.borrow_mut(),rec.p.unwrap(),to_light);
+                        let pdf_light = light.pdf_value(light_rec.borrow_mut(),ray_light.origin(),to_light);
                         if pdf_light > 0.00001{
                             let brdf = material.
                                 scattering_pdf(ray.unwrap().borrow(),rec.borrow(),ray_light.borrow());
@@ -87,7 +104,7 @@ fn ray_color(ray:Ray,background:&Color,sences_manager:Arc<SencesManger>,depth:i3
                 if depth <= 0 {
                     return l_in_dir;
                 }
-                if rand_range_f64(0.0,1.0) > 0.8 {
+                if sample_1d() > 0.8 {
                     return l_in_dir;
                 }
                 let l_dir;
@@ -118,7 +135,24 @@ fn main() {
     let mut lookat  = Vec3::new();
     let mut vfov = 40.0;
     let mut aperture = 0.0;
+    //为 0 时对焦到 lookat
+    let mut dist_to_focus = 0.0;
+    //设置了镜头参数时按物理相机算视角和光圈 不用 vfov 和 aperture
+    let mut lens:Option<Lens> = None;
+    //同一个场景的多个视图 (lookfrom, lookat) 为空时只用上面的 lookfrom lookat
+    let mut views:Vec<(Point3, Point3)> = vec![];
+    //每个视图都拆成左右眼
+    let mut stereo:Option<StereoRig> = None;
+    let mut layout = ViewLayout::SideBySide;
     let seneces = 6;
+    //0 透视 1 平行投影 2 等距鱼眼 3 等立体角鱼眼 4 360 度全景
+    let projection_kind = 0;
+    //像素重建滤波 0 盒子 1 三角形 2 高斯 3 Mitchell-Netravali 4 Blackman-Harris
+    let filter_kind = 0;
+    //把像素分成 n x n 个格子 每一轮采样落在其中一个格子里 为 false 时在整个像素里随机取
+    let stratified = true;
+    //路径上的采样 0 独立随机 1 Owen 打乱的 Sobol 2 打乱的 Halton 3 蓝噪声平移的 Sobol
+    let sampler_kind = 1;
     let mut objs = vec![];
     let mut background= color3!(0,0,0);
 
@@ -137,6 +171,7 @@ fn main() {
             background= color3!(0.7, 0.8, 1);
             vfov = 20.0;
             aperture = 0.1;
+            dist_to_focus = 10.0;
         }
         2 => {
             objs = two_spheres();
@@ -152,6 +187,13 @@ fn main() {
             background= color3!(0.7, 0, 1);
             vfov = 20.0;
         }
+        4 =>{
+            sences_manager = SencesManger::form(None, procedural_textures());
+            lookfrom = point3!(0,3,14);
+            lookat = point3!(0,1,0);
+            background= color3!(0.7, 0.8, 1);
+            vfov = 30.0;
+        }
         5 =>{
             background= color3!(0, 0, 0);
             objs = simple_light();
@@ -171,17 +213,164 @@ fn main() {
             lookat =  point3!(278, 278, 0);
             vfov = 40.0;
         }
+        7 =>{
+            sences_manager = obj_scene();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,4,12);
+            lookat = point3!(0,1,0);
+            vfov = 40.0;
+        }
+        8 =>{
+            sences_manager = instanced_cats();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,8,16);
+            lookat = point3!(0,0,0);
+            vfov = 40.0;
+        }
+        9 =>{
+            sences_manager = motion_blur();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,2,10);
+            lookat = point3!(0,0.8,0);
+            vfov = 30.0;
+        }
+        10 =>{
+            sences_manager = primitives();
+            background = color3!(0.3, 0.35, 0.4);
+            lookfrom = point3!(0,3,9);
+            lookat = point3!(0,0.8,0);
+            vfov = 40.0;
+        }
+        11 =>{
+            sences_manager = csg_scene();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,3,10);
+            lookat = point3!(0,1,0);
+            vfov = 35.0;
+        }
+        12 =>{
+            sences_manager = sdf_scene();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,3,10);
+            lookat = point3!(0,1,0);
+            vfov = 35.0;
+        }
+        13 =>{
+            sences_manager = subdivided_cats();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,3,9);
+            lookat = point3!(0,1,0);
+            vfov = 35.0;
+        }
+        14 =>{
+            sences_manager = furry_cat();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,2.5,7);
+            lookat = point3!(0,0.8,0);
+            vfov = 35.0;
+        }
+        15 =>{
+            sences_manager = bokeh();
+            background = color3!(0.02, 0.02, 0.05);
+            lookfrom = point3!(0,1.2,12);
+            lookat = point3!(0,0.5,0);
+            //85mm f/1.4 六片光圈 自动对焦到画面中间的球上
+            lens = Some(Lens{
+                focal_length: 85.0,
+                f_stop: 1.4,
+                blades: 6,
+                blade_rotation: 15.0,
+                ..Lens::new()
+            });
+        }
+        16 =>{
+            sences_manager = primitives();
+            background = color3!(0.3, 0.35, 0.4);
+            lookfrom = point3!(0,3,9);
+            lookat = point3!(0,0.8,0);
+            vfov = 40.0;
+            //VR 预览 左右眼上下排 会聚到画面中间的物体上
+            stereo = Some(StereoRig{ interocular: 0.3, convergence: (lookfrom - lookat).length() });
+            layout = ViewLayout::OverUnder;
+        }
+        17 =>{
+            sences_manager = csg_scene();
+            background = color3!(0.7, 0.8, 1);
+            lookfrom = point3!(0,3,10);
+            lookat = point3!(0,1,0);
+            vfov = 35.0;
+            //正面 侧面 斜上方三个视图一次渲染
+            views = vec![(lookfrom, lookat), (point3!(10,3,0), lookat), (point3!(6,8,6), lookat)];
+        }
         _ =>  {}
     }
+    if views.is_empty() {
+        views.push((lookfrom, lookat));
+    }
+    if dist_to_focus <= 0.0 {
+        dist_to_focus = (lookfrom - lookat).length();
+    }
+    let projection = match projection_kind {
+        //平行投影的画面和透视在对焦平面上的画面一样大
+        1 => { Projection::Orthographic(2.0 * (degrees_to_radians(vfov) / 2.0).tan() * dist_to_focus) }
+        2 => { Projection::Fisheye(FisheyeMapping::Equidistant, 180.0) }
+        3 => { Projection::Fisheye(FisheyeMapping::Equisolid, 180.0) }
+        4 => {
+            aspect_ratio = 2.0;
+            Projection::Equirectangular
+        }
+        _ => { Projection::Perspective }
+    };
     let image_height = (image_width as f64 / aspect_ratio) as i32;
     let max_depth = 100;
-    let dist_to_focus = 10.0;
-    let collected_iterator: Vec<i32> = (0..image_width*image_height).collect();
-    let mut pixel_buffer = Arc::new(RwLock::new(vec![Color::new();image_width as usize * image_height as usize]));
-    let mut render_times = Arc::new(RwLock::new(1));
     //Camera
-    let camera_arc = Arc::new(Camera::new(
-        lookfrom, lookat, Vec3::form(0.0,1.0,0.0), vfov, aspect_ratio, aperture, dist_to_focus));
+    let mut cameras = vec![];
+    for &(lookfrom, lookat) in views.iter() {
+        let mut camera = match lens {
+            None => { Camera::new(lookfrom, lookat, Vec3::form(0.0,1.0,0.0), vfov, aspect_ratio, aperture, dist_to_focus) }
+            Some(ref lens) => { Camera::form_lens(lookfrom, lookat, Vec3::form(0.0,1.0,0.0), aspect_ratio, lens) }
+        };
+        camera.set_shutter(0.0, 1.0);
+        camera.set_projection(projection);
+        //画面中心没有物体时保持对焦到 lookat
+        if let Some(Lens{ focus_distance: None, .. }) = lens {
+            camera.autofocus(&sences_manager);
+        }
+        match stereo {
+            None => { cameras.push(camera) }
+            Some(ref rig) => { cameras.extend_from_slice(&rig.eyes(&camera)) }
+        }
+    }
+    //所有视图拼成一张图 每个视图 image_width x image_height 共用一个场景和 BVH
+    let (view_cols, view_rows) = layout.grid(cameras.len());
+    let frame_width = image_width * view_cols as i32;
+    let frame_height = image_height * view_rows as i32;
+    let filter:Arc<dyn Filter> = match filter_kind {
+        1 => { Arc::new(TentFilter::form(1.0)) }
+        2 => { Arc::new(GaussianFilter::form(1.5, 0.5)) }
+        3 => { Arc::new(MitchellFilter::form(2.0, 1.0 / 3.0, 1.0 / 3.0)) }
+        4 => { Arc::new(BlackmanHarrisFilter::form(2.0)) }
+        _ => { Arc::new(BoxFilter::form(0.5)) }
+    };
+    let sampler:Box<dyn Sampler> = match sampler_kind {
+        1 => { Box::new(SobolSampler::new()) }
+        2 => { Box::new(HaltonSampler::form(256)) }
+        3 => { Box::new(BlueNoiseSampler::form(Arc::new(blue_noise_mask(64)), 64)) }
+        _ => { Box::new(RandomSampler) }
+    };
+    //低差异序列本身就是分层的 只有独立随机数才需要手动分格子
+    let stratified = stratified && sampler_kind == 0;
+    //第 k 轮采样用 stratum_order[k] 号格子 格子顺序打乱 采样数不是平方数时空出来的格子也是随机的
+    let strata = if stratified { (samples_per_pixel as f64).sqrt().ceil() as i32 } else { 1 };
+    let mut stratum_order:Vec<i32> = (0..strata * strata).collect();
+    for i in (1..stratum_order.len()).rev() {
+        stratum_order.swap(i, rand_i3_range(0, i as i32 + 1) as usize);
+    }
+    let stratum_order = Arc::new(stratum_order);
+    //每个像素累加 滤波权重 * 颜色 和 滤波权重
+    let mut pixel_buffer = Arc::new(RwLock::new(vec![(Color::new(), 0.0);frame_width as usize * frame_height as usize]));
+    let mut render_times = Arc::new(RwLock::new(1));
+    let camera_arc = Arc::new(cameras);
 
     //World
     //读取stl模型三角面
@@ -194,15 +383,19 @@ fn main() {
         let camera_t = camera_arc.clone();
         let sences_t = sences_manager.clone();
         let arc_pixel_buffer = pixel_buffer.clone();
+        let filter_t = filter.clone();
+        let stratum_order_t = stratum_order.clone();
+        let sampler_t = sampler.clone_box();
         let s_chan = tx.clone();
         let global_render_times = render_times.clone();
         let _t = thread::spawn(move ||{
+            set_sampler(sampler_t);
             let mut file = std::fs::File::create(format!("file{}",thread_n)).expect("create failed");
-            let per_num = (image_height as f32 / count as f32).ceil() as i32;
+            let per_num = (frame_height as f32 / count as f32).ceil() as i32;
             let render_segment_start = per_num * thread_n;
             let mut render_segment_end =  per_num * (thread_n + 1);
-            if render_segment_end > image_height{
-                render_segment_end = image_height
+            if render_segment_end > frame_height{
+                render_segment_end = frame_height
             }
         for count in  0 .. samples_per_pixel{
             loop{
@@ -211,25 +404,56 @@ fn main() {
                 }
                 thread::sleep(time::Duration::from_millis(10));
             }
-            let mut tmp_pixel_buffer = vec![];
+            //采样会溅射到相邻的像素 本轮先累加到覆盖 [起始行 - reach, 结束行 + reach) 的缓冲里
+            let reach = (filter_t.radius() + 0.5).ceil() as i32;
+            let splat_start = (render_segment_start - reach).max(0);
+            let splat_end = (render_segment_end + reach).min(frame_height);
+            let mut splat_buffer = vec![(Color::new(), 0.0);((splat_end - splat_start) * frame_width) as usize];
+            let stratum = stratum_order_t[count as usize % stratum_order_t.len()];
+            let (stratum_x, stratum_y) = ((stratum % strata) as f64, (stratum / strata) as f64);
             for row in render_segment_start .. render_segment_end{
-                for col in 0.. image_width {
+                for col in 0.. frame_width {
+                    //先找到像素属于哪个视图 再算它在视图里的位置
+                        let view = (row / image_height) as usize * view_cols + (col / image_width) as usize;
+                        let view_col = col % image_width;
+                        let view_row = row % image_height;
                     //往一个像素 偏移非常小的dw方向上 发射不同的光 采样
-                        let u = (col as f64 + rand_f64()) / (image_width -1) as f64;
-                        let v = (((image_height - 1) - row)  as f64 + rand_f64()) / (image_height - 1) as f64 ;
-                        let ray = camera_t.get_ray(u,v);
-                        let mut pixel_color = ray_color(ray,&background,sences_t.clone(),max_depth);
-                        let mut pixel_buffer = arc_pixel_buffer.read().unwrap();
-                        let pixel_index = row * image_width  + col;
-                        let sum_pixel = pixel_buffer[pixel_index as usize] + pixel_color;
-                        tmp_pixel_buffer.push(sum_pixel);
+                        start_pixel_sample(col as u32, row as u32, count as u32);
+                        let (jitter_x, jitter_y) = sample_2d();
+                        let px = view_col as f64 + (stratum_x + jitter_x) / strata as f64;
+                        let py = view_row as f64 + (stratum_y + jitter_y) / strata as f64;
+                        let u = px / image_width as f64;
+                        let v = (image_height as f64 - py) / image_height as f64;
+                        let pixel_color = match camera_t[view].get_ray(u,v) {
+                            None => { Color::new() }
+                            Some(ray) => { ray_color(ray,&background,sences_t.clone(),max_depth) }
+                        };
+                        //溅射到中心在滤波半径内的像素 不越过视图的边界
+                        let radius = filter_t.radius();
+                        let x0 = (px - 0.5 - radius).ceil().max(0.0) as i32;
+                        let x1 = (px - 0.5 + radius).floor().min((image_width - 1) as f64) as i32;
+                        let y0 = (py - 0.5 - radius).ceil().max(0.0) as i32;
+                        let y1 = (py - 0.5 + radius).floor().min((image_height - 1) as f64) as i32;
+                        for y in y0 ..= y1 {
+                            for x in x0 ..= x1 {
+                                let weight = filter_t.evaluate(x as f64 + 0.5 - px, y as f64 + 0.5 - py);
+                                if weight == 0.0 {
+                                    continue;
+                                }
+                                let splat_row = row - view_row + y - splat_start;
+                                let splat_col = col - view_col + x;
+                                let pixel = &mut splat_buffer[(splat_row * frame_width + splat_col) as usize];
+                                pixel.0 += pixel_color * weight;
+                                pixel.1 += weight;
+                            }
+                        }
                     }
                 }
-                let mut c = 0;
                 let mut pixel_buffer = arc_pixel_buffer.write().unwrap();
-                for pixel in  tmp_pixel_buffer{
-                    pixel_buffer[(render_segment_start * image_width + c) as usize ] = pixel;
-                    c += 1;
+                for (c, splat) in splat_buffer.into_iter().enumerate(){
+                    let pixel = &mut pixel_buffer[splat_start as usize * frame_width as usize + c];
+                    pixel.0 += splat.0;
+                    pixel.1 += splat.1;
                 }
                 s_chan.send(());
             }
@@ -242,9 +466,10 @@ fn main() {
         let reader = pixel_buffer.write().unwrap();
         let mut output: File = File::create("image.ppm").unwrap();
         let mut render_times = render_times.write().unwrap();
-        output.write("P3 \n500 500 \n255\n".as_ref());
-        for c in reader.iter(){
-            let d_pixel = write_color(*c,*render_times);
+        output.write(format!("P3 \n{} {} \n255\n", frame_width, frame_height).as_ref());
+        for (c, weight) in reader.iter(){
+            //权重和可能因为负的滤波权重接近 0 这时当作黑色
+            let d_pixel = if *weight > 0.0 { write_color(*c / *weight, 1) } else { Color::new() };
             let s = format!("{}",d_pixel);
             output.write(s.as_bytes());
         }
@@ -2,9 +2,10 @@ use crate::ray::{Ray, Point3};
 use crate::hit::HitRecorder;
 use crate::Color;
 use crate::vec3::Vec3;
-use crate::common::rand_f64;
+use crate::common::degrees_to_radians;
+use crate::sampler::{sample_1d, sample_2d};
 use std::sync::Arc;
-use crate::texture::{SolidColor, Texture};
+use crate::texture::{SolidColor, Texture, luminance};
 use std::f64::consts::PI;
 
 
@@ -34,12 +35,12 @@ impl Lambertian{
 }
 
 impl Materials for Lambertian{
-    fn scatter(&self, _ray_in: &Ray, rec:&mut HitRecorder) -> Option<Ray> {
+    fn scatter(&self, ray_in: &Ray, rec:&mut HitRecorder) -> Option<Ray> {
         let mut scatter_direction =  rec.normal.unwrap() + Vec3::random_uniform();
         if scatter_direction.near_zero(){
             scatter_direction = rec.normal.unwrap();
         }
-        return Some(Ray::form(rec.p.unwrap(), scatter_direction.unit_vector()))
+        return Some(rec.spawn_ray(ray_in, scatter_direction.unit_vector()))
     }
 
     fn scattering_pdf(&self,_r_in: &Ray, rec: &HitRecorder, scattered: &Ray) -> f64 {
@@ -89,7 +90,7 @@ impl Metal{
 impl Materials for Metal{
     fn scatter(&self, ray_in: &Ray, rec: &mut HitRecorder) -> Option<Ray> {
         let reflected = Vec3::reflect(ray_in.direction().unit_vector(),rec.normal.unwrap());
-        let scattered = Ray::form(rec.p.unwrap(), reflected + Vec3::random_in_unit_sphere() * self.fuzz  );
+        let scattered = rec.spawn_ray(ray_in, reflected + Vec3::sample_in_unit_sphere() * self.fuzz);
         let x = Vec3::dot(scattered.direction(),rec.normal.unwrap());
         rec.is_specular = true;
         if  x > 0.0{
@@ -138,14 +139,14 @@ impl Materials for Dielectric{
         let sin_theta = 1.0 - (cos_theta * cos_theta);
             let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let direction;
-        if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > rand_f64() {
+        if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > sample_1d() {
             direction = Vec3::reflect(unit_direction, rec.normal.unwrap());
         }else{
              direction = Vec3::refract(unit_direction,
                                          rec.normal.unwrap(),refraction_ratio);
         }
         rec.is_specular = true;
-        Some(Ray::form(rec.p.unwrap(),direction))
+        Some(rec.spawn_ray(ray_in, direction))
     }
 
     fn scattering_pdf(&self,r_in: &Ray, rec: &HitRecorder, scattered: &Ray) -> f64 {
@@ -198,3 +199,125 @@ impl Materials for DiffuseLight{
         self.emit.clone().unwrap().value(u,v,&p)
     }
 }
+
+//头发的散射 按 Marschner / Chiang 的模型分成 R TT TRT 三个波瓣
+//要求物体把发丝方向放在 tangent 里 v 是横跨发丝宽度的坐标(0 到 1)
+pub(crate) struct Hair{
+    //发丝内部的吸收系数
+    sigma_a:Color,
+    //纵向和方位角的粗糙度 [0,1]
+    beta_m:f64,
+    beta_n:f64,
+    //毛鳞片的倾斜角(角度)
+    alpha:f64,
+    eta:f64,
+}
+
+impl Hair{
+    pub(crate) fn form(sigma_a:Color, beta_m:f64, beta_n:f64) -> Self{
+        Self{
+            sigma_a,
+            beta_m,
+            beta_n,
+            alpha: 2.0,
+            eta: 1.55,
+        }
+    }
+
+    //按想要的发色反推吸收系数 (Chiang 2016)
+    pub(crate) fn form_color(color:Color, beta_m:f64, beta_n:f64) -> Self{
+        let b = beta_n;
+        let denom = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3) + 5.574 * b.powi(4) + 0.245 * b.powi(5);
+        let sigma = |c:f64| (c.max(1e-4).ln() / denom).powi(2);
+        Self::form(Color::form(sigma(color.x), sigma(color.y), sigma(color.z)), beta_m, beta_n)
+    }
+
+    //三个波瓣各自的衰减: 反射 / 透射两次 / 透射两次加一次内部反射
+    fn lobe_attenuation(&self, sin_theta_o:f64, cos_theta_o:f64, h:f64) -> [Color;3]{
+        let cos_gamma_o = (1.0 - h * h).max(0.0).sqrt();
+        let f = Dielectric::reflectance(cos_theta_o * cos_gamma_o, self.eta);
+        //折射进发丝之后的角度
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = h / etap;
+        let cos_gamma_t = (1.0 - sin_gamma_t * sin_gamma_t).max(0.0).sqrt();
+        let len = 2.0 * cos_gamma_t / cos_theta_t;
+        let t = Color::form((-self.sigma_a.x * len).exp(), (-self.sigma_a.y * len).exp(), (-self.sigma_a.z * len).exp());
+        let tt = t * ((1.0 - f) * (1.0 - f));
+        [Color::form(f, f, f), tt, tt * t * f]
+    }
+
+    //截断到 [-π, π] 的 logistic 分布采样
+    fn sample_trimmed_logistic(u:f64, s:f64) -> f64{
+        let cdf = |x:f64| 1.0 / (1.0 + (-x / s).exp());
+        let k = cdf(PI) - cdf(-PI);
+        let x = -s * (1.0 / (u * k + cdf(-PI)) - 1.0).ln();
+        x.clamp(-PI, PI)
+    }
+}
+
+impl Materials for Hair{
+    fn scatter(&self, ray_in: &Ray, rec: &mut HitRecorder) -> Option<Ray> {
+        let wo = -ray_in.direction().unit_vector();
+        let tangent = rec.tangent?.unit_vector();
+        let sin_theta_o = Vec3::dot(wo, tangent).clamp(-1.0, 1.0);
+        let cos_theta_o = (1.0 - sin_theta_o * sin_theta_o).max(1e-6).sqrt();
+        //垂直于发丝的平面里 以出射方向为 φ = 0
+        let perp = wo - tangent * sin_theta_o;
+        let x_axis = if perp.near_zero() { Vec3::orthonormal_basis(tangent).0 } else { perp.unit_vector() };
+        let y_axis = Vec3::cross(tangent, x_axis);
+        let h = (rec.v * 2.0 - 1.0).clamp(-0.999, 0.999);
+        let gamma_o = h.asin();
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let gamma_t = (h / etap).clamp(-1.0, 1.0).asin();
+
+        //按亮度选择波瓣
+        let ap = self.lobe_attenuation(sin_theta_o, cos_theta_o, h);
+        let weights = ap.map(luminance);
+        let total:f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let r = sample_1d() * total;
+        let p = if r < weights[0] { 0 } else if r < weights[0] + weights[1] { 1 } else { 2 };
+
+        //纵向: 毛鳞片让各个波瓣偏转 -2α α 4α 再按粗糙度采样
+        let alpha = degrees_to_radians(self.alpha);
+        let shift = [-2.0 * alpha, alpha, 4.0 * alpha][p];
+        let sin_theta_op = sin_theta_o * shift.cos() - cos_theta_o * shift.sin();
+        let cos_theta_op = (1.0 - sin_theta_op * sin_theta_op).max(0.0).sqrt();
+        let v0 = (0.726 * self.beta_m + 0.812 * self.beta_m.powi(2) + 3.7 * self.beta_m.powi(20)).powi(2);
+        let v = [v0, 0.25 * v0, 4.0 * v0][p].max(1e-4);
+        let (u, w) = sample_2d();
+        let u = u.max(1e-5);
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * w).cos();
+        let sin_theta_i = (-cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op).clamp(-1.0, 1.0);
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+
+        //方位角: 几何上的偏转加上 logistic 分布的粗糙度
+        let s = (PI / 8.0).sqrt() * (0.265 * self.beta_n + 1.194 * self.beta_n.powi(2) + 5.372 * self.beta_n.powi(22));
+        let pf = p as f64;
+        let phi = 2.0 * pf * gamma_t - 2.0 * gamma_o + pf * PI + Self::sample_trimmed_logistic(sample_1d(), s);
+
+        let direction = tangent * sin_theta_i + (x_axis * phi.cos() + y_axis * phi.sin()) * cos_theta_i;
+        rec.is_specular = true;
+        rec.attenuation = Some(ap[p] * (total / weights[p]));
+        Some(rec.spawn_ray(ray_in, direction))
+    }
+
+    //只能按 scatter 采样的方向计算 不参与对光源的直接采样
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecorder, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn get_color(&self, rec: &HitRecorder) -> Color {
+        rec.attenuation.unwrap_or(Color::new())
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        Color::new()
+    }
+}
@@ -0,0 +1,644 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::common::{degrees_to_radians, rand_f64, Axis};
+use crate::hit::{Hittable, HitRecorder};
+use crate::material::Materials;
+use crate::shape::{AABB, intersect_triangle, set_triangle_hit};
+use crate::precision::{Precision, Real, to_real3, from_real3};
+use crate::bvh::{BvhNode, BvhConfig};
+use crate::wide_bvh::WideBvh;
+use crate::texture::{Texture, luminance};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::collections::HashMap;
+
+//导入模型后对整体位置的调整
+pub(crate) enum MeshFit{
+    None,
+    //包围盒中心移到原点
+    CenterOnOrigin,
+    //等比缩放放进给定的包围盒 并居中
+    FitToBox(Point3, Point3),
+}
+
+//细分方式 参数为细分的次数 每次一个三角形变成 4 个(Loop) 或一个面的每个角变成一个四边形(Catmull-Clark)
+pub(crate) enum Subdivision{
+    None,
+    Loop(u32),
+    //三角形先分成 3 个四边形 之后四边形每次分成 4 个 细分完再把四边形拆成两个三角形
+    CatmullClark(u32),
+}
+
+//导入网格时对顶点做的变换 依次为: 缩放 -> 绕 x y z 轴旋转(角度) -> fit -> 平移
+//变换之后再细分 最后沿顶点法线按纹理亮度 * 系数做位移 precision 是建 BVH 后渲染时顶点数据的精度
+//计算顶点法线时 相邻面的夹角超过 crease_angle(角度) 就当成硬边不做平滑 180 表示全部平滑
+pub(crate) struct MeshTransform{
+    pub(crate) scale:Vec3,
+    pub(crate) rotate:Vec3,
+    pub(crate) translate:Vec3,
+    pub(crate) fit:MeshFit,
+    pub(crate) subdivision:Subdivision,
+    pub(crate) displacement:Option<(Arc<dyn Texture>, f64)>,
+    pub(crate) precision:Precision,
+    pub(crate) crease_angle:f64,
+}
+
+impl MeshTransform{
+    pub(crate) fn new() -> Self{
+        Self{
+            scale: Vec3::form(1.0, 1.0, 1.0),
+            rotate: Vec3::new(),
+            translate: Vec3::new(),
+            fit: MeshFit::None,
+            subdivision: Subdivision::None,
+            displacement: None,
+            precision: Precision::F64,
+            crease_angle: 60.0,
+        }
+    }
+
+    pub(crate) fn form(scale:f64, rotate:Vec3, translate:Vec3) -> Self{
+        Self{
+            scale: Vec3::form(scale, scale, scale),
+            rotate,
+            translate,
+            fit: MeshFit::None,
+            subdivision: Subdivision::None,
+            displacement: None,
+            precision: Precision::F64,
+            crease_angle: 60.0,
+        }
+    }
+
+    fn rotate_vec(&self, mut v:Vec3) -> Vec3{
+        let rx = degrees_to_radians(self.rotate.x);
+        let ry = degrees_to_radians(self.rotate.y);
+        v = Vec3::rotate_x(v, rx.sin(), rx.cos());
+        v = Vec3::rotate_y(v, ry.sin(), ry.cos());
+        Vec3::rotate_z(v, degrees_to_radians(self.rotate.z))
+    }
+
+    //缩放的分量乘积为负时镜像了模型 三角形绕序需要反过来
+    pub(crate) fn flips_winding(&self) -> bool{
+        self.scale.x * self.scale.y * self.scale.z < 0.0
+    }
+
+    //法线用缩放的逆 再旋转 fit 只有等比缩放和平移 不影响法线方向
+    pub(crate) fn apply_normal(&self, n:Vec3) -> Vec3{
+        let scaled = Vec3::form(n.x / self.scale.x, n.y / self.scale.y, n.z / self.scale.z);
+        self.rotate_vec(scaled).unit_vector()
+    }
+
+    pub(crate) fn apply(&self, points:&[Point3]) -> Vec<Point3>{
+        let mut out:Vec<Point3> = points.iter().map(|p| self.rotate_vec(*p * self.scale)).collect();
+        if out.is_empty() {
+            return out;
+        }
+        let mut min = out[0];
+        let mut max = out[0];
+        for p in out.iter(){
+            min = Vec3::min(min, *p);
+            max = Vec3::max(max, *p);
+        }
+        let center = (min + max) / 2.0;
+        match self.fit {
+            MeshFit::None => {}
+            MeshFit::CenterOnOrigin => {
+                for p in out.iter_mut(){
+                    *p = *p - center;
+                }
+            }
+            MeshFit::FitToBox(box_min, box_max) => {
+                let extent = max - min;
+                let target = box_max - box_min;
+                let mut s = f64::MAX;
+                for i in 0..3{
+                    if extent.get_field(i) > 0.0 {
+                        s = f64::min(s, target.get_field(i) / extent.get_field(i));
+                    }
+                }
+                if s == f64::MAX {
+                    s = 1.0;
+                }
+                let box_center = (box_min + box_max) / 2.0;
+                for p in out.iter_mut(){
+                    *p = (*p - center) * s + box_center;
+                }
+            }
+        }
+        for p in out.iter_mut(){
+            *p += self.translate;
+        }
+        out
+    }
+}
+
+//带索引的三角网格 顶点 法线 uv 由所有三角形共享 normals/uvs 为空表示没有
+pub(crate) struct TriangleMesh{
+    pub(crate) positions:Vec<Point3>,
+    pub(crate) normals:Vec<Vec3>,
+    pub(crate) uvs:Vec<(f64,f64)>,
+    pub(crate) indices:Vec<[u32;3]>,
+    pub(crate) materials:Vec<Arc<dyn Materials>>,
+    //每个三角形在 materials 里的下标
+    pub(crate) material_ids:Vec<u32>,
+    pub(crate) precision:Precision,
+}
+
+impl TriangleMesh{
+    pub(crate) fn form(positions:Vec<Point3>,indices:Vec<[u32;3]>,material:Arc<dyn Materials>) -> Self{
+        let material_ids = vec![0; indices.len()];
+        Self{
+            positions,
+            normals: vec![],
+            uvs: vec![],
+            indices,
+            materials: vec![material],
+            material_ids,
+            precision: Precision::F64,
+        }
+    }
+
+    //按面积加权平均相邻三角形的面法线 得到平滑的顶点法线 夹角超过 crease_angle 的面不参与平均
+    //顶点在硬边两侧的法线不同时拆成多个顶点
+    pub(crate) fn compute_vertex_normals(&mut self, crease_angle:f64){
+        let corners = self.corner_normals(crease_angle);
+        self.set_corner_normals(&corners);
+    }
+
+    //每个三角形三个角上的法线 uv 接缝处坐标相同的顶点会被拆开 按坐标找相邻的面 接缝两边的法线才一致
+    pub(crate) fn corner_normals(&self, crease_angle:f64) -> Vec<[Vec3;3]>{
+        let canon = self.position_ids();
+        let cos_crease = degrees_to_radians(crease_angle).cos();
+        //叉积的长度是面积的两倍 直接累加就是面积加权
+        let face_normals:Vec<Vec3> = self.indices.iter().map(|tri| {
+            let [a, b, c] = tri.map(|i| self.positions[i as usize]);
+            Vec3::cross(b - a, c - a)
+        }).collect();
+        let mut vertex_faces:HashMap<usize,Vec<usize>> = HashMap::new();
+        for (f, tri) in self.indices.iter().enumerate(){
+            for &i in tri.iter(){
+                vertex_faces.entry(canon[i as usize]).or_default().push(f);
+            }
+        }
+        self.indices.iter().enumerate().map(|(f, tri)| {
+            let nf = face_normals[f];
+            tri.map(|i| {
+                let mut n = Vec3::new();
+                for &g in vertex_faces[&canon[i as usize]].iter(){
+                    let ng = face_normals[g];
+                    //退化的面没有方向 和所有面一起平滑
+                    if nf.near_zero() || Vec3::dot(nf, ng) >= cos_crease * nf.length() * ng.length() {
+                        n += ng;
+                    }
+                }
+                if n.near_zero() { n } else { n.unit_vector() }
+            })
+        }).collect()
+    }
+
+    //按每个角的法线设置顶点法线 同一个顶点在不同面上的法线不同时复制出新的顶点
+    pub(crate) fn set_corner_normals(&mut self, corners:&[[Vec3;3]]){
+        let has_uvs = !self.uvs.is_empty();
+        let mut normals:Vec<Option<Vec3>> = vec![None; self.positions.len()];
+        let mut copies:HashMap<(u32,[u64;3]),u32> = HashMap::new();
+        let (positions, uvs) = (&mut self.positions, &mut self.uvs);
+        for (f, tri) in self.indices.iter_mut().enumerate(){
+            for k in 0..3{
+                let i = tri[k];
+                let n = corners[f][k];
+                match normals[i as usize] {
+                    None => { normals[i as usize] = Some(n) }
+                    Some(m) if [m.x, m.y, m.z] == [n.x, n.y, n.z] => {}
+                    Some(_) => {
+                        tri[k] = *copies.entry((i, [n.x.to_bits(), n.y.to_bits(), n.z.to_bits()])).or_insert_with(|| {
+                            positions.push(positions[i as usize]);
+                            if has_uvs {
+                                uvs.push(uvs[i as usize]);
+                            }
+                            normals.push(Some(n));
+                            (positions.len() - 1) as u32
+                        });
+                    }
+                }
+            }
+        }
+        self.normals = normals.into_iter().map(|n| n.unwrap_or_else(Vec3::new)).collect();
+    }
+
+    //每个顶点对应的第一个坐标相同的顶点下标
+    fn position_ids(&self) -> Vec<usize>{
+        let mut first:HashMap<[u64;3],usize> = HashMap::new();
+        self.positions.iter().enumerate().map(|(i, p)| {
+            *first.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert(i)
+        }).collect()
+    }
+
+    //按导入参数细分和位移 网格变了之后重新计算平滑法线
+    pub(crate) fn refine(&mut self, transform:&MeshTransform){
+        self.precision = transform.precision;
+        if !matches!(transform.subdivision, Subdivision::None) {
+            self.subdivide(&transform.subdivision);
+            self.compute_vertex_normals(transform.crease_angle);
+        }
+        if let Some((texture, scale)) = &transform.displacement {
+            self.displace(texture.as_ref(), *scale, transform.crease_angle);
+        }
+    }
+
+    //沿顶点法线移动 距离为纹理亮度 * scale
+    //坐标相同的顶点(uv 接缝和硬边两侧拆开的)只按第一个顶点的 uv 取一次纹理 沿完全平滑的法线移动同样的距离 不会裂开
+    pub(crate) fn displace(&mut self, texture:&dyn Texture, scale:f64, crease_angle:f64){
+        self.compute_vertex_normals(180.0);
+        let canon = self.position_ids();
+        let mut offsets = vec![0.0; self.positions.len()];
+        for i in 0..self.positions.len(){
+            if canon[i] == i {
+                let (u, v) = if self.uvs.is_empty() { (0.0, 0.0) } else { self.uvs[i] };
+                offsets[i] = luminance(texture.value(u, v, &self.positions[i])) * scale;
+            }
+        }
+        for i in 0..self.positions.len(){
+            self.positions[i] += self.normals[i] * offsets[canon[i]];
+        }
+        self.compute_vertex_normals(crease_angle);
+    }
+
+    //按 scheme 细分若干次 Loop 每次把三角形分成 4 个
+    //Catmull-Clark 第一次把三角形分成 3 个四边形 之后每次把四边形分成 4 个 全部细分完再拆成三角形
+    //uv 按原来的顶点线性插值 法线清空 由调用方重新计算
+    fn subdivide(&mut self, scheme:&Subdivision){
+        let levels = match scheme {
+            Subdivision::None => { return }
+            Subdivision::Loop(n) | Subdivision::CatmullClark(n) => { *n }
+        };
+        let loop_scheme = matches!(scheme, Subdivision::Loop(_));
+        let mut faces:Vec<Vec<u32>> = self.indices.iter().map(|tri| tri.to_vec()).collect();
+        for _i in 0..levels{
+            faces = self.subdivide_once(&faces, loop_scheme);
+        }
+        //四边形沿 v0-v2 对角线拆开
+        let mut indices = vec![];
+        let mut material_ids = vec![];
+        for (face, &id) in faces.iter().zip(self.material_ids.iter()){
+            for k in 1 .. face.len() - 1{
+                indices.push([face[0], face[k], face[k + 1]]);
+                material_ids.push(id);
+            }
+        }
+        self.indices = indices;
+        self.material_ids = material_ids;
+        self.normals = vec![];
+    }
+
+    //细分一次 faces 是多边形 每个面的材质在 material_ids 里 返回新的面并更新 material_ids
+    //拓扑按坐标合并后的顶点计算 这样 uv 接缝两边算出的新坐标一样 不会裂开
+    fn subdivide_once(&mut self, faces:&[Vec<u32>], loop_scheme:bool) -> Vec<Vec<u32>>{
+        let canon = self.position_ids();
+        let pos = &self.positions;
+        let key = |a:usize, b:usize| if a < b { (a, b) } else { (b, a) };
+
+        //合并后的每条边相邻的面 每个顶点相邻的顶点和面
+        let mut edge_faces:HashMap<(usize,usize),Vec<usize>> = HashMap::new();
+        let mut vertex_faces:HashMap<usize,Vec<usize>> = HashMap::new();
+        let mut vertex_neighbors:HashMap<usize,Vec<usize>> = HashMap::new();
+        for (f, face) in faces.iter().enumerate(){
+            let c:Vec<usize> = face.iter().map(|&i| canon[i as usize]).collect();
+            for k in 0..c.len(){
+                let (a, b) = (c[k], c[(k + 1) % c.len()]);
+                edge_faces.entry(key(a, b)).or_default().push(f);
+                vertex_faces.entry(c[k]).or_default().push(f);
+                for n in [a, b]{
+                    let other = if n == a { b } else { a };
+                    let list = vertex_neighbors.entry(n).or_default();
+                    if !list.contains(&other) {
+                        list.push(other);
+                    }
+                }
+            }
+        }
+        let centroid = |f:usize| {
+            let mut sum = Vec3::new();
+            for &i in faces[f].iter(){
+                sum += pos[i as usize];
+            }
+            sum / faces[f].len() as f64
+        };
+        //三角形里和边 (a, b) 相对的那个顶点
+        let opposite = |f:usize, a:usize, b:usize| {
+            let o = faces[f].iter().map(|&i| canon[i as usize]).find(|&v| v != a && v != b).unwrap_or(a);
+            pos[o]
+        };
+
+        let mut edge_points:HashMap<(usize,usize),Point3> = HashMap::new();
+        for (&(a, b), adjacent) in edge_faces.iter(){
+            let mid = (pos[a] + pos[b]) / 2.0;
+            let p = if adjacent.len() != 2 {
+                mid
+            } else if loop_scheme {
+                (pos[a] + pos[b]) * (3.0 / 8.0) + (opposite(adjacent[0], a, b) + opposite(adjacent[1], a, b)) / 8.0
+            } else {
+                (pos[a] + pos[b] + centroid(adjacent[0]) + centroid(adjacent[1])) / 4.0
+            };
+            edge_points.insert((a, b), p);
+        }
+
+        let mut vertex_points:HashMap<usize,Point3> = HashMap::new();
+        for (&v, neighbors) in vertex_neighbors.iter(){
+            let p = pos[v];
+            let boundary:Vec<usize> = neighbors.iter().copied().filter(|&n| edge_faces[&key(v, n)].len() != 2).collect();
+            let n = neighbors.len() as f64;
+            let new_p = if boundary.len() == 2 {
+                p * 0.75 + (pos[boundary[0]] + pos[boundary[1]]) / 8.0
+            } else if !boundary.is_empty() || neighbors.len() < 3 {
+                //角点或者非流形的点保持不动
+                p
+            } else if loop_scheme {
+                let beta = if neighbors.len() == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * n) };
+                let mut sum = Vec3::new();
+                for &o in neighbors.iter(){
+                    sum += pos[o];
+                }
+                p * (1.0 - n * beta) + sum * beta
+            } else {
+                let adjacent = &vertex_faces[&v];
+                let mut q = Vec3::new();
+                for &f in adjacent.iter(){
+                    q += centroid(f);
+                }
+                q /= adjacent.len() as f64;
+                let mut r = Vec3::new();
+                for &o in neighbors.iter(){
+                    r += (p + pos[o]) / 2.0;
+                }
+                r /= n;
+                (q + r * 2.0 + p * (n - 3.0)) / n
+            };
+            vertex_points.insert(v, new_p);
+        }
+
+        let has_uvs = !self.uvs.is_empty();
+        let mut positions:Vec<Point3> = (0..pos.len()).map(|i| *vertex_points.get(&canon[i]).unwrap_or(&pos[i])).collect();
+        let mut uvs = self.uvs.clone();
+        let mut new_faces = vec![];
+        let mut material_ids = vec![];
+        //新的边点按原来的顶点对去重 接缝两边各有一份
+        let mut edge_vertex:HashMap<(u32,u32),u32> = HashMap::new();
+        for (f, face) in faces.iter().enumerate(){
+            let k = face.len();
+            let mut mids = vec![0u32; k];
+            for i in 0..k{
+                let (a, b) = (face[i], face[(i + 1) % k]);
+                let id_key = if a < b { (a, b) } else { (b, a) };
+                mids[i] = *edge_vertex.entry(id_key).or_insert_with(|| {
+                    positions.push(edge_points[&key(canon[a as usize], canon[b as usize])]);
+                    if has_uvs {
+                        let (ua, va) = self.uvs[a as usize];
+                        let (ub, vb) = self.uvs[b as usize];
+                        uvs.push(((ua + ub) / 2.0, (va + vb) / 2.0));
+                    }
+                    (positions.len() - 1) as u32
+                });
+            }
+            let children = if loop_scheme {
+                let [a, b, c] = [face[0], face[1], face[2]];
+                let [ab, bc, ca] = [mids[0], mids[1], mids[2]];
+                new_faces.extend_from_slice(&[vec![a, ab, ca], vec![b, bc, ab], vec![c, ca, bc], vec![ab, bc, ca]]);
+                4
+            } else {
+                positions.push(centroid(f));
+                if has_uvs {
+                    let (mut u, mut v) = (0.0, 0.0);
+                    for &i in face.iter(){
+                        u += self.uvs[i as usize].0;
+                        v += self.uvs[i as usize].1;
+                    }
+                    uvs.push((u / k as f64, v / k as f64));
+                }
+                let m = (positions.len() - 1) as u32;
+                //每个角和相邻两条边的边点 面点组成一个四边形
+                for i in 0..k{
+                    new_faces.push(vec![face[i], mids[i], m, mids[(i + k - 1) % k]]);
+                }
+                k
+            };
+            for _i in 0..children{
+                material_ids.push(self.material_ids[f]);
+            }
+        }
+        self.positions = positions;
+        self.uvs = uvs;
+        self.material_ids = material_ids;
+        new_faces
+    }
+
+    //按面积均匀地在表面上取 count 个点 返回位置和插值后的法线
+    pub(crate) fn sample_surface(&self, count:usize) -> Vec<(Point3, Vec3)>{
+        let mut cdf = vec![];
+        let mut total = 0.0;
+        for tri in self.indices.iter(){
+            let [a, b, c] = tri.map(|i| self.positions[i as usize]);
+            total += Vec3::cross(b - a, c - a).length();
+            cdf.push(total);
+        }
+        let mut samples = vec![];
+        if total <= 0.0 {
+            return samples;
+        }
+        for _i in 0..count{
+            let r = rand_f64() * total;
+            let f = cdf.partition_point(|&x| x < r).min(cdf.len() - 1);
+            let idx = self.indices[f].map(|i| i as usize);
+            let [a, b, c] = idx.map(|i| self.positions[i]);
+            //三角形内均匀的重心坐标
+            let su = rand_f64().sqrt();
+            let (w1, w2) = (1.0 - su, su * rand_f64());
+            let w0 = 1.0 - w1 - w2;
+            let p = a * w0 + b * w1 + c * w2;
+            let n = if self.normals.is_empty() {
+                Vec3::cross(b - a, c - a)
+            } else {
+                self.normals[idx[0]] * w0 + self.normals[idx[1]] * w1 + self.normals[idx[2]] * w2
+            };
+            samples.push((p, n.unit_vector()));
+        }
+        samples
+    }
+
+    //转换成 R 精度的网格数据 每个三角形只保存网格的引用和自己的下标
+    fn triangles<R:Real>(self) -> Vec<Arc<dyn Hittable>>{
+        let data = MeshData::<R>{
+            positions: self.positions.into_iter().map(to_real3).collect(),
+            normals: self.normals.into_iter().map(to_real3).collect(),
+            uvs: self.uvs.into_iter().map(|(u, v)| [R::from_f64(u), R::from_f64(v)]).collect(),
+            indices: self.indices,
+            materials: self.materials,
+            material_ids: self.material_ids,
+        };
+        let mesh = Arc::new(data);
+        let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+        for index in 0..mesh.indices.len(){
+            objs.push(Arc::new(MeshTriangle{ mesh: mesh.clone(), index }));
+        }
+        objs
+    }
+
+    pub(crate) fn build_bvh(self) -> Option<WideBvh>{
+        if self.indices.is_empty() {
+            return None;
+        }
+        let mut objs = match self.precision {
+            Precision::F64 => { self.triangles::<f64>() }
+            Precision::F32 => { self.triangles::<f32>() }
+        };
+        let config = BvhConfig::new();
+        let tree = BvhNode::form_with(objs.as_mut_slice(), &config)?;
+        Some(WideBvh::from_tree(&tree))
+    }
+}
+
+//建好 BVH 之后渲染用的网格数据 按 R 的精度存放 不再修改
+pub(crate) struct MeshData<R:Real>{
+    positions:Vec<[R;3]>,
+    normals:Vec<[R;3]>,
+    uvs:Vec<[R;2]>,
+    indices:Vec<[u32;3]>,
+    materials:Vec<Arc<dyn Materials>>,
+    material_ids:Vec<u32>,
+}
+
+pub(crate) struct MeshTriangle<R:Real>{
+    mesh:Arc<MeshData<R>>,
+    index:usize,
+}
+
+impl<R:Real> MeshTriangle<R>{
+    fn raw_vertices(&self) -> [[R;3];3]{
+        self.mesh.indices[self.index].map(|i| self.mesh.positions[i as usize])
+    }
+
+    fn vertices(&self) -> [Point3;3]{
+        self.raw_vertices().map(from_real3)
+    }
+}
+
+impl<R:Real> Debug for MeshTriangle<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"MeshTriangle({})",self.index)
+    }
+}
+
+impl<R:Real> Hittable for MeshTriangle<R>{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let hit = match intersect_triangle(self.raw_vertices(), ray, t_min, t_max) {
+            None => { return false }
+            Some(hit) => { hit }
+        };
+        let mesh = &self.mesh;
+        let idx = mesh.indices[self.index];
+        let normals = if mesh.normals.is_empty() { None } else { Some(idx.map(|i| from_real3(mesh.normals[i as usize]))) };
+        let uvs = if mesh.uvs.is_empty() { None } else {
+            Some(idx.map(|i| (mesh.uvs[i as usize][0].to_f64(), mesh.uvs[i as usize][1].to_f64())))
+        };
+        rec.material = Some(mesh.materials[mesh.material_ids[self.index] as usize].clone());
+        set_triangle_hit(rec, ray, hit, self.vertices(), normals, uvs);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let [p1, p2, p3] = self.vertices();
+        Some(AABB::form(Vec3::min(p1, Vec3::min(p2, p3)), Vec3::max(p1, Vec3::max(p2, p3))))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        let [p1, p2, p3] = self.vertices();
+        a.call((p1 + p2 + p3) / 3.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+
+    fn triangle(&self) -> Option<[Point3;3]> {
+        Some(self.vertices())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::Color;
+
+    //单位立方体 8 个顶点 12 个三角形 法线朝外
+    fn cube() -> TriangleMesh{
+        let positions = (0..8).map(|i| Vec3::form((i & 1) as f64, ((i >> 1) & 1) as f64, ((i >> 2) & 1) as f64)).collect();
+        let indices = vec![
+            [0, 2, 1], [1, 2, 3], [4, 5, 6], [5, 7, 6],
+            [0, 1, 4], [1, 5, 4], [2, 6, 3], [3, 6, 7],
+            [0, 4, 2], [2, 4, 6], [1, 3, 5], [3, 7, 5],
+        ];
+        TriangleMesh::form(positions, indices, Arc::new(Lambertian::form_color(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn crease_angle_keeps_hard_edges(){
+        let mut mesh = cube();
+        mesh.compute_vertex_normals(60.0);
+        //每个角在三个面上各有一份 法线都是面法线
+        assert_eq!(mesh.positions.len(), 24);
+        for tri in mesh.indices.iter(){
+            let [a, b, c] = tri.map(|i| mesh.positions[i as usize]);
+            let face = Vec3::cross(b - a, c - a).unit_vector();
+            for &i in tri.iter(){
+                assert!((Vec3::dot(mesh.normals[i as usize], face) - 1.0).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn full_smoothing_shares_vertices(){
+        let mut mesh = cube();
+        mesh.compute_vertex_normals(180.0);
+        assert_eq!(mesh.positions.len(), 8);
+        let corner = mesh.normals[7];
+        assert!(corner.x > 0.0 && corner.y > 0.0 && corner.z > 0.0);
+    }
+
+    #[test]
+    fn catmull_clark_keeps_quads_between_levels(){
+        let mut mesh = cube();
+        mesh.subdivide(&Subdivision::CatmullClark(2));
+        //12 个三角形 -> 36 个四边形 -> 144 个四边形 -> 288 个三角形
+        assert_eq!(mesh.indices.len(), 288);
+        assert_eq!(mesh.material_ids.len(), 288);
+        //闭合的网格细分后还是闭合的 每条边正好被两个三角形共享
+        let canon = mesh.position_ids();
+        let mut edges:HashMap<(usize,usize),u32> = HashMap::new();
+        for tri in mesh.indices.iter(){
+            let c = tri.map(|i| canon[i as usize]);
+            for k in 0..3{
+                *edges.entry((c[k].min(c[(k + 1) % 3]), c[k].max(c[(k + 1) % 3]))).or_default() += 1;
+            }
+        }
+        assert!(edges.values().all(|&n| n == 2));
+    }
+
+    #[test]
+    fn displacement_does_not_open_uv_seams(){
+        //两个三角形共享一条边 边上的顶点在两边的 uv 不同
+        let positions = vec![Vec3::form(0.0, 0.0, 0.0), Vec3::form(1.0, 0.0, 0.0), Vec3::form(0.0, 1.0, 0.0),
+                             Vec3::form(1.0, 0.0, 0.0), Vec3::form(0.0, 1.0, 0.0), Vec3::form(1.0, 1.0, 0.0)];
+        let mut mesh = TriangleMesh::form(positions, vec![[0, 1, 2], [3, 5, 4]], Arc::new(Lambertian::form_color(0.5, 0.5, 0.5)));
+        mesh.uvs = vec![(0.1, 0.1), (0.1, 0.1), (0.1, 0.1), (0.6, 0.1), (0.6, 0.1), (0.6, 0.1)];
+        let texture = crate::texture::UvCheckerTexture::form_color(Color::new(), Color::form(1.0, 1.0, 1.0), 2.0, 1.0);
+        mesh.displace(&texture, 0.5, 180.0);
+        let canon = mesh.position_ids();
+        assert_eq!(canon[3], canon[1]);
+        assert_eq!(canon[4], canon[2]);
+    }
+}
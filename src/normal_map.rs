@@ -0,0 +1,161 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::hit::{Hittable, HitRecorder};
+use crate::shape::AABB;
+use crate::common::Axis;
+use crate::texture::{Texture, luminance};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+//有限差分求高度梯度的步长(uv 空间)
+const BUMP_DELTA:f64 = 1e-3;
+
+//取出击中点的切线空间 T B N 没有切线的物体就随便构造一组
+fn tangent_frame(rec:&HitRecorder) -> (Vec3, Vec3, Vec3){
+    let n = rec.normal.unwrap();
+    match (rec.tangent, rec.bitangent) {
+        (Some(dpdu), Some(dpdv)) => {
+            let t = dpdu - n * Vec3::dot(n, dpdu);
+            if t.near_zero() {
+                let (t, b) = Vec3::orthonormal_basis(n);
+                return (t, b, n);
+            }
+            let t = t.unit_vector();
+            let mut b = Vec3::cross(n, t);
+            //背面击中时法线被翻转了 保持 B 和 dp/dv 同向
+            if Vec3::dot(b, dpdv) < 0.0 {
+                b = -b;
+            }
+            (t, b, n)
+        }
+        _ => {
+            let (t, b) = Vec3::orthonormal_basis(n);
+            (t, b, n)
+        }
+    }
+}
+
+//切线空间法线贴图 贴图颜色 [0,1] 映射到 [-1,1] 的法线分量
+pub(crate) struct NormalMap{
+    obj_ptr:Option<Arc<dyn Hittable>>,
+    map:Option<Arc<dyn Texture>>,
+}
+
+impl NormalMap{
+    pub(crate) fn form(p:Arc<dyn Hittable>,map:Arc<dyn Texture>) -> Self{
+        Self{
+            obj_ptr: Some(p),
+            map: Some(map),
+        }
+    }
+}
+
+impl Debug for NormalMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"NormalMap({:?})",self.obj_ptr)
+    }
+}
+
+impl Hittable for NormalMap{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        if !self.obj_ptr.as_ref().unwrap().hit(ray, t_min, t_max, rec) {
+            return false;
+        }
+        let (t, b, n) = tangent_frame(rec);
+        let c = self.map.as_ref().unwrap().value(rec.u, rec.v, &rec.p.unwrap()) * 2.0 - 1.0;
+        let perturbed = (t * c.x + b * c.y + n * c.z).unit_vector();
+        if Vec3::dot(perturbed, n) > 0.0 {
+            rec.normal = Some(perturbed);
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.obj_ptr.as_ref().unwrap().bounding_box()
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        self.obj_ptr.as_ref().unwrap().get_center_point(a)
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        self.obj_ptr.as_ref().unwrap().pdf_value(rec, p, dir)
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        self.obj_ptr.as_ref().unwrap().random_sample()
+    }
+}
+
+//凹凸贴图 把纹理亮度当作沿法线的高度 用有限差分扰动法线
+pub(crate) struct BumpMap{
+    obj_ptr:Option<Arc<dyn Hittable>>,
+    height:Option<Arc<dyn Texture>>,
+    strength:f64,
+}
+
+impl BumpMap{
+    pub(crate) fn form(p:Arc<dyn Hittable>,height:Arc<dyn Texture>,strength:f64) -> Self{
+        Self{
+            obj_ptr: Some(p),
+            height: Some(height),
+            strength,
+        }
+    }
+}
+
+impl Debug for BumpMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"BumpMap({:?})",self.obj_ptr)
+    }
+}
+
+impl Hittable for BumpMap{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        if !self.obj_ptr.as_ref().unwrap().hit(ray, t_min, t_max, rec) {
+            return false;
+        }
+        let (dpdu, dpdv) = match (rec.tangent, rec.bitangent) {
+            (Some(dpdu), Some(dpdv)) => { (dpdu, dpdv) }
+            _ => {
+                let (t, b, _n) = tangent_frame(rec);
+                (t, b)
+            }
+        };
+        let n = rec.normal.unwrap();
+        let p = rec.p.unwrap();
+        let height = self.height.as_ref().unwrap();
+        let h = luminance(height.value(rec.u, rec.v, &p));
+        let h_u = luminance(height.value(rec.u + BUMP_DELTA, rec.v, &(p + dpdu * BUMP_DELTA)));
+        let h_v = luminance(height.value(rec.u, rec.v + BUMP_DELTA, &(p + dpdv * BUMP_DELTA)));
+        //位移后的曲面 p' = p + h * n 的偏导数
+        let displaced_u = dpdu + n * ((h_u - h) / BUMP_DELTA * self.strength);
+        let displaced_v = dpdv + n * ((h_v - h) / BUMP_DELTA * self.strength);
+        let mut perturbed = Vec3::cross(displaced_u, displaced_v);
+        if perturbed.near_zero() {
+            return true;
+        }
+        perturbed = perturbed.unit_vector();
+        if Vec3::dot(perturbed, n) < 0.0 {
+            perturbed = -perturbed;
+        }
+        rec.normal = Some(perturbed);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.obj_ptr.as_ref().unwrap().bounding_box()
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        self.obj_ptr.as_ref().unwrap().get_center_point(a)
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        self.obj_ptr.as_ref().unwrap().pdf_value(rec, p, dir)
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        self.obj_ptr.as_ref().unwrap().random_sample()
+    }
+}
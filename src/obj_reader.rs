@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::material::{Materials, Lambertian, Metal, Dielectric, DiffuseLight};
+use crate::wide_bvh::WideBvh;
+use crate::Color;
+use crate::mesh::{MeshTransform, TriangleMesh};
+use crate::precision::Precision;
+
+#[derive(Debug)]
+pub(crate) enum ObjError{
+    Io(PathBuf, std::io::Error),
+    Parse{file:PathBuf, line:usize, msg:String},
+}
+
+impl Display for ObjError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(path, e) => { write!(f,"读取 {} 失败: {}",path.display(),e) }
+            ObjError::Parse{file, line, msg} => { write!(f,"{}:{} 解析失败: {}",file.display(),line,msg) }
+        }
+    }
+}
+
+impl std::error::Error for ObjError{}
+
+//obj 里的一个 g/o 分组 三角化后的面和各自的材质
+pub(crate) struct ObjGroup{
+    pub(crate) name:String,
+    faces:Vec<([FaceVertex;3],Arc<dyn Materials>)>,
+}
+
+//面的一个顶点 下标都已经转成从 0 开始
+#[derive(Copy, Clone)]
+struct FaceVertex{
+    v:usize,
+    vt:Option<usize>,
+    vn:Option<usize>,
+}
+
+pub(crate) struct ObjReader{
+    path:PathBuf,
+    positions:Vec<Vec3>,
+    normals:Vec<Vec3>,
+    uvs:Vec<(f64,f64)>,
+    materials:HashMap<String,Arc<dyn Materials>>,
+    default_material:Arc<dyn Materials>,
+    groups:Vec<ObjGroup>,
+    //不影响导入的问题 比如找不到材质 由调用方决定要不要提示
+    warnings:Vec<String>,
+}
+
+impl ObjReader{
+    //没有 usemtl 或者找不到对应材质的面使用 default_material
+    pub(crate) fn new_obj_reader(file_path:String,default_material:Arc<dyn Materials>) -> Result<Self,ObjError>{
+        let path = PathBuf::from(file_path);
+        let content = fs::read_to_string(&path).map_err(|e| ObjError::Io(path.clone(), e))?;
+        let mut reader = Self{
+            path,
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            materials: HashMap::new(),
+            default_material,
+            groups: vec![],
+            warnings: vec![],
+        };
+        reader.parse(&content)?;
+        Ok(reader)
+    }
+
+    fn parse_error(&self,line:usize,msg:String) -> ObjError{
+        ObjError::Parse{file: self.path.clone(), line, msg}
+    }
+
+    fn parse(&mut self,content:&str) -> Result<(),ObjError>{
+        let mut current_material = self.default_material.clone();
+        let mut current_group = ObjGroup{ name: "default".to_string(), faces: vec![] };
+        for (i, raw) in content.lines().enumerate(){
+            let line_no = i + 1;
+            let line = raw.split('#').next().unwrap().trim();
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                None => { continue }
+                Some(k) => { k }
+            };
+            let args:Vec<&str> = tokens.collect();
+            match keyword {
+                "v" => {
+                    let f = self.parse_floats(&args, 3, line_no)?;
+                    self.positions.push(Vec3::form(f[0], f[1], f[2]));
+                }
+                "vn" => {
+                    let f = self.parse_floats(&args, 3, line_no)?;
+                    self.normals.push(Vec3::form(f[0], f[1], f[2]));
+                }
+                "vt" => {
+                    let f = self.parse_floats(&args, 1, line_no)?;
+                    self.uvs.push((f[0], if f.len() > 1 { f[1] } else { 0.0 }));
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(self.parse_error(line_no, "面至少需要三个顶点".to_string()));
+                    }
+                    let mut face = vec![];
+                    for a in args.iter(){
+                        face.push(self.parse_face_vertex(a, line_no)?);
+                    }
+                    //多边形按扇形三角化
+                    for k in 1 .. face.len() - 1{
+                        current_group.faces.push(([face[0], face[k], face[k + 1]], current_material.clone()));
+                    }
+                }
+                "g" | "o" => {
+                    let name = if args.is_empty() { "default".to_string() } else { args.join(" ") };
+                    let finished = std::mem::replace(&mut current_group, ObjGroup{ name, faces: vec![] });
+                    if !finished.faces.is_empty() {
+                        self.groups.push(finished);
+                    }
+                }
+                "mtllib" => {
+                    for lib in args.iter(){
+                        let lib_path = self.path.parent().unwrap_or_else(|| Path::new("")).join(lib);
+                        self.load_mtl(&lib_path)?;
+                    }
+                }
+                "usemtl" => {
+                    let name = args.join(" ");
+                    current_material = match self.materials.get(&name) {
+                        Some(m) => { m.clone() }
+                        None => {
+                            self.warnings.push(format!("{}:{} 找不到材质 {} 使用默认材质",self.path.display(),line_no,name));
+                            self.default_material.clone()
+                        }
+                    };
+                }
+                _ => {}
+            }
+        }
+        if !current_group.faces.is_empty() {
+            self.groups.push(current_group);
+        }
+        Ok(())
+    }
+
+    fn parse_floats(&self,args:&[&str],min_count:usize,line_no:usize) -> Result<Vec<f64>,ObjError>{
+        if args.len() < min_count {
+            return Err(self.parse_error(line_no, format!("需要至少 {} 个数值", min_count)));
+        }
+        let mut nums = vec![];
+        for a in args.iter(){
+            nums.push(a.parse::<f64>().map_err(|_| self.parse_error(line_no, format!("无效的数值 {}", a)))?);
+        }
+        Ok(nums)
+    }
+
+    //obj 下标从 1 开始 负数表示从当前末尾往前数
+    fn resolve_index(&self,token:&str,len:usize,line_no:usize) -> Result<usize,ObjError>{
+        let i = token.parse::<i64>().map_err(|_| self.parse_error(line_no, format!("无效的下标 {}", token)))?;
+        let index = if i < 0 { len as i64 + i } else { i - 1 };
+        if index < 0 || index >= len as i64 {
+            return Err(self.parse_error(line_no, format!("下标 {} 越界", token)));
+        }
+        Ok(index as usize)
+    }
+
+    //支持 v, v/vt, v//vn, v/vt/vn 四种写法
+    fn parse_face_vertex(&self,token:&str,line_no:usize) -> Result<FaceVertex,ObjError>{
+        let mut parts = token.split('/');
+        let v = self.resolve_index(parts.next().unwrap(), self.positions.len(), line_no)?;
+        let vt = match parts.next() {
+            Some(t) if !t.is_empty() => { Some(self.resolve_index(t, self.uvs.len(), line_no)?) }
+            _ => { None }
+        };
+        let vn = match parts.next() {
+            Some(t) if !t.is_empty() => { Some(self.resolve_index(t, self.normals.len(), line_no)?) }
+            _ => { None }
+        };
+        Ok(FaceVertex{ v, vt, vn })
+    }
+
+    //不同的 (v, vt, vn) 组合拆成不同的网格顶点 只要有一个面顶点缺少 vt 整个网格就不带 uv
+    //部分顶点缺少 vn 时这些顶点用相邻面算出的法线 其他顶点保留文件里的法线
+    //fit 按整个模型的包围盒计算 单独取出的分组和整体导入时位置一致
+    fn build_mesh<'a>(&self,groups:impl Iterator<Item=&'a ObjGroup>,transform:&MeshTransform) -> TriangleMesh{
+        let all_positions = transform.apply(&self.positions);
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        let mut has_normals = false;
+        let mut missing_normals = vec![];
+        let mut has_uvs = true;
+        let mut indices = vec![];
+        let mut materials:Vec<Arc<dyn Materials>> = vec![];
+        let mut material_ids = vec![];
+        let mut vertex_map:HashMap<(usize,Option<usize>,Option<usize>),u32> = HashMap::new();
+        for g in groups{
+            for (f, material) in g.faces.iter(){
+                let mut f = *f;
+                if transform.flips_winding() {
+                    f.swap(1, 2);
+                }
+                let tri = f.map(|fv| {
+                    *vertex_map.entry((fv.v, fv.vt, fv.vn)).or_insert_with(|| {
+                        positions.push(all_positions[fv.v]);
+                        match fv.vn {
+                            Some(n) => { has_normals = true; normals.push(transform.apply_normal(self.normals[n])) }
+                            None => { missing_normals.push(positions.len() - 1); normals.push(Vec3::new()) }
+                        }
+                        match fv.vt {
+                            Some(t) => { uvs.push(self.uvs[t]) }
+                            None => { has_uvs = false; uvs.push((0.0, 0.0)) }
+                        }
+                        (positions.len() - 1) as u32
+                    })
+                });
+                indices.push(tri);
+                let id = match materials.iter().position(|m| Arc::ptr_eq(m, material)) {
+                    Some(id) => { id }
+                    None => {
+                        materials.push(material.clone());
+                        materials.len() - 1
+                    }
+                };
+                material_ids.push(id as u32);
+            }
+        }
+        let mut mesh = TriangleMesh{
+            positions,
+            normals: if has_normals { normals } else { vec![] },
+            uvs: if has_uvs { uvs } else { vec![] },
+            indices,
+            materials,
+            material_ids,
+            precision: Precision::F64,
+        };
+        if has_normals && !missing_normals.is_empty() {
+            let mut missing = vec![false; mesh.positions.len()];
+            for i in missing_normals{
+                missing[i] = true;
+            }
+            let mut corners = mesh.corner_normals(transform.crease_angle);
+            for (tri, corner) in mesh.indices.iter().zip(corners.iter_mut()){
+                for k in 0..3{
+                    if !missing[tri[k] as usize] {
+                        corner[k] = mesh.normals[tri[k] as usize];
+                    }
+                }
+            }
+            mesh.set_corner_normals(&corners);
+        }
+        mesh.refine(transform);
+        mesh
+    }
+
+    fn load_mtl(&mut self,path:&Path) -> Result<(),ObjError>{
+        let content = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let mut current:Option<(String,MtlParams)> = None;
+        for (i, raw) in content.lines().enumerate(){
+            let line_no = i + 1;
+            let line = raw.split('#').next().unwrap().trim();
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                None => { continue }
+                Some(k) => { k }
+            };
+            let args:Vec<&str> = tokens.collect();
+            let parse = |n:usize| -> Result<Vec<f64>,ObjError>{
+                if args.len() < n {
+                    return Err(ObjError::Parse{file: path.to_path_buf(), line: line_no, msg: format!("需要至少 {} 个数值", n)});
+                }
+                args.iter().take(n).map(|a| a.parse::<f64>().map_err(|_| ObjError::Parse{
+                    file: path.to_path_buf(), line: line_no, msg: format!("无效的数值 {}", a)})).collect()
+            };
+            if keyword == "newmtl" {
+                if let Some((name, params)) = current.take() {
+                    self.materials.insert(name, params.to_material());
+                }
+                current = Some((args.join(" "), MtlParams::new()));
+                continue;
+            }
+            let params = match current.as_mut() {
+                None => { continue }
+                Some((_, params)) => { params }
+            };
+            match keyword {
+                "Kd" => { let f = parse(3)?; params.kd = Color::form(f[0], f[1], f[2]); }
+                "Ks" => { let f = parse(3)?; params.ks = Color::form(f[0], f[1], f[2]); }
+                "Ke" => { let f = parse(3)?; params.ke = Color::form(f[0], f[1], f[2]); }
+                "Ns" => { params.ns = parse(1)?[0]; }
+                "Ni" => { params.ni = parse(1)?[0]; }
+                "d" => { params.d = parse(1)?[0]; }
+                "Tr" => { params.d = 1.0 - parse(1)?[0]; }
+                "illum" => { params.illum = parse(1)?[0] as i32; }
+                _ => {}
+            }
+        }
+        if let Some((name, params)) = current.take() {
+            self.materials.insert(name, params.to_material());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn warnings(&self) -> &[String]{
+        &self.warnings
+    }
+
+    //整个模型的所有三角形构建一棵 BVH
+    pub(crate) fn read_all_shape_info(&self,transform:&MeshTransform) -> Option<WideBvh>{
+        self.build_mesh(self.groups.iter(), transform).build_bvh()
+    }
+
+    pub(crate) fn read_group(&self,name:&str,transform:&MeshTransform) -> Option<WideBvh>{
+        self.build_mesh(self.groups.iter().filter(|g| g.name == name), transform).build_bvh()
+    }
+}
+
+struct MtlParams{
+    kd:Color,
+    ks:Color,
+    ke:Color,
+    ns:f64,
+    ni:f64,
+    d:f64,
+    illum:i32,
+}
+
+impl MtlParams{
+    fn new() -> Self{
+        Self{
+            kd: Color::form(0.8, 0.8, 0.8),
+            ks: Color::new(),
+            ke: Color::new(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+        }
+    }
+
+    //自发光 -> DiffuseLight, 透明或 illum 4/6/7 -> Dielectric, illum 3 或只有镜面色 -> Metal, 其他 -> Lambertian
+    fn to_material(&self) -> Arc<dyn Materials>{
+        if !self.ke.near_zero() {
+            return Arc::new(DiffuseLight::form(self.ke));
+        }
+        if self.d < 1.0 || self.illum == 4 || self.illum == 6 || self.illum == 7 {
+            return Arc::new(Dielectric::form(self.ni));
+        }
+        if self.illum == 3 || (self.kd.near_zero() && !self.ks.near_zero()) {
+            //Phong 高光指数转换成粗糙度
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            return Arc::new(Metal::form_c(self.ks, fuzz));
+        }
+        Arc::new(Lambertian::form_color(self.kd.x, self.kd.y, self.kd.z))
+    }
+}
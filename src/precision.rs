@@ -0,0 +1,164 @@
+use std::fmt::Debug;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+use crate::vec3::Vec3;
+use crate::ray::{Point3, Ray};
+
+//网格数据存放和三角形求交用的精度 f32 时顶点内存减半
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Precision{
+    F64,
+    F32,
+}
+
+//f32 和 f64 共用的浮点运算 求交代码按它写一份
+pub(crate) trait Real: Copy + PartialOrd + Debug + Send + Sync + 'static
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>{
+    const ZERO:Self;
+    const ONE:Self;
+    //机器精度的一半 即一次舍入的最大相对误差
+    const MACHINE_EPSILON:Self;
+    fn from_f64(v:f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+    fn max(self, o:Self) -> Self;
+}
+
+impl Real for f64{
+    const ZERO:Self = 0.0;
+    const ONE:Self = 1.0;
+    const MACHINE_EPSILON:Self = f64::EPSILON * 0.5;
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+    fn max(self, o: Self) -> Self {
+        f64::max(self, o)
+    }
+}
+
+impl Real for f32{
+    const ZERO:Self = 0.0;
+    const ONE:Self = 1.0;
+    const MACHINE_EPSILON:Self = f32::EPSILON * 0.5;
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+    fn max(self, o: Self) -> Self {
+        f32::max(self, o)
+    }
+}
+
+//n 次运算累积的相对误差上界 n*e / (1 - n*e)
+pub(crate) fn gamma<R:Real>(n:i32) -> R{
+    let ne = R::from_f64(n as f64) * R::MACHINE_EPSILON;
+    ne / (R::ONE - ne)
+}
+
+pub(crate) fn to_real3<R:Real>(v:Vec3) -> [R;3]{
+    [R::from_f64(v.x), R::from_f64(v.y), R::from_f64(v.z)]
+}
+
+pub(crate) fn from_real3<R:Real>(v:[R;3]) -> Vec3{
+    Vec3::form(v[0].to_f64(), v[1].to_f64(), v[2].to_f64())
+}
+
+//p = o + t*d 的舍入误差上界 t 本身的误差由各个形状自己保证不太大
+pub(crate) fn ray_point_error(ray:&Ray, t:f64) -> Vec3{
+    (ray.origin().abs() + (ray.direction() * t).abs()) * gamma::<f64>(7)
+}
+
+//比 v 大的下一个浮点数 -0.0 当成 0.0 处理
+pub(crate) fn next_float_up(v:f64) -> f64{
+    if v.is_nan() || v == f64::INFINITY {
+        return v;
+    }
+    let v = if v == 0.0 { 0.0 } else { v };
+    let bits = v.to_bits();
+    f64::from_bits(if v >= 0.0 { bits + 1 } else { bits - 1 })
+}
+
+pub(crate) fn next_float_down(v:f64) -> f64{
+    -next_float_up(-v)
+}
+
+//沿法线把起点推出误差盒子: 偏移量是误差盒子在法线上投影的长度 再朝偏移方向多取一个浮点数
+pub(crate) fn offset_ray_origin(p:Point3, p_error:Vec3, n:Vec3, w:Vec3) -> Point3{
+    let d = Vec3::dot(n.abs(), p_error);
+    let mut offset = n * d;
+    if Vec3::dot(w, n) < 0.0 {
+        offset = -offset;
+    }
+    let mut po = p + offset;
+    for i in 0..3{
+        let o = offset.get_field(i);
+        let v = po.get_field(i);
+        if o > 0.0 {
+            po.set_i_field(i, next_float_up(v));
+        } else if o < 0.0 {
+            po.set_i_field(i, next_float_down(v));
+        }
+    }
+    po
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::common::rand_f64;
+
+    #[test]
+    fn gamma_bounds(){
+        let e = f64::EPSILON * 0.5;
+        assert_eq!(gamma::<f64>(1), e / (1.0 - e));
+        assert!(gamma::<f64>(3) > 3.0 * e && gamma::<f64>(3) < 3.0 * e * 1.001);
+        assert!(gamma::<f32>(3) as f64 > gamma::<f64>(3) * 1e8);
+        //n 次乘法的实际误差不超过 gamma(n)
+        let a = 1.0f32 / 3.0;
+        let exact = (1.0f64 / 3.0).powi(4);
+        let approx = (a * a * a * a) as f64;
+        assert!((approx - exact).abs() <= exact * gamma::<f32>(4) as f64);
+    }
+
+    #[test]
+    fn next_float_steps_one_ulp(){
+        assert_eq!(next_float_up(1.0), 1.0 + f64::EPSILON);
+        assert_eq!(next_float_down(1.0), 1.0 - f64::EPSILON / 2.0);
+        assert_eq!(next_float_up(-0.0), f64::from_bits(1));
+        assert_eq!(next_float_down(0.0), -f64::from_bits(1));
+        assert_eq!(next_float_up(-f64::from_bits(1)).to_bits(), (-0.0f64).to_bits());
+        assert_eq!(next_float_up(f64::INFINITY), f64::INFINITY);
+        assert_eq!(next_float_down(f64::NEG_INFINITY), f64::NEG_INFINITY);
+        assert!(next_float_up(f64::NAN).is_nan());
+    }
+
+    //每个分量都朝出射方向那一侧移动 距离不少于误差盒子在法线上投影的长度乘以法线分量
+    //所以起点沿法线离开 p 至少投影的长度 出了误差盒子
+    #[test]
+    fn offset_leaves_error_box(){
+        for i in 0..1000{
+            let scale = 10f64.powi(i % 12 - 6);
+            let p = Vec3::random_in_unit_sphere() * scale;
+            let p_error = Vec3::form(rand_f64(), rand_f64(), rand_f64()) * (scale * 1e-12);
+            let n = Vec3::random_unit_vector();
+            let w = Vec3::random_unit_vector();
+            let side = if Vec3::dot(w, n) < 0.0 { -1.0 } else { 1.0 };
+            let po = offset_ray_origin(p, p_error, n, w);
+            let d = Vec3::dot(n.abs(), p_error);
+            for k in 0..3{
+                let moved = (po.get_field(k) - p.get_field(k)) * side * n.get_field(k).signum();
+                assert!(moved >= n.get_field(k).abs() * d);
+            }
+        }
+    }
+}
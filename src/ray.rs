@@ -22,13 +22,19 @@ impl Vec3{
 pub(crate) struct Ray {
     origin: Point3,
     dir:Vec3,
+    //快门打开期间的时刻 运动模糊用
+    time:f64,
 }
 
 impl Ray {
     pub(crate) fn form(origin: Point3, dir:Vec3) -> Self{
+        Self::form_time(origin, dir, 0.0)
+    }
+    pub(crate) fn form_time(origin: Point3, dir:Vec3, time:f64) -> Self{
         Ray {
             origin,
-            dir
+            dir,
+            time
         }
     }
     pub(crate) fn at(self,t:f64) -> Point3 {
@@ -40,4 +46,7 @@ impl Ray {
     pub(crate) fn direction(self) -> Vec3{
         self.dir
     }
+    pub(crate) fn time(self) -> f64{
+        self.time
+    }
 }
\ No newline at end of file
@@ -0,0 +1,428 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use crate::common::{rand_f64, rand_i3_range};
+
+//比 1 小的最大的 f64 采样值都落在 [0, 1)
+const ONE_MINUS_EPSILON:f64 = 1.0 - f64::EPSILON / 2.0;
+
+//路径上每一次取随机数都是一个维度 同一个像素的不同采样在每个维度上分布得越均匀 噪点越少
+//相机 镜头 BSDF 光源采样都从当前线程的采样器里按顺序取维度
+pub(crate) trait Sampler: Send{
+    //开始像素 (x, y) 的第 index 个采样 维度从 0 重新算
+    fn start_pixel_sample(&mut self, x:u32, y:u32, index:u32);
+    //下一个维度 [0, 1)
+    fn get_1d(&mut self) -> f64;
+    //下一对维度 二维上分布均匀 用在像素 光圈 方向这类二维的采样上
+    fn get_2d(&mut self) -> (f64, f64){
+        let a = self.get_1d();
+        (a, self.get_1d())
+    }
+    //每个渲染线程一份
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+thread_local! {
+    static SAMPLER: RefCell<Box<dyn Sampler>> = RefCell::new(Box::new(RandomSampler));
+}
+
+//设置当前线程用的采样器 没设置时是独立随机数
+pub(crate) fn set_sampler(sampler:Box<dyn Sampler>){
+    SAMPLER.with(|s| *s.borrow_mut() = sampler);
+}
+
+pub(crate) fn start_pixel_sample(x:u32, y:u32, index:u32){
+    SAMPLER.with(|s| s.borrow_mut().start_pixel_sample(x, y, index));
+}
+
+pub(crate) fn sample_1d() -> f64{
+    SAMPLER.with(|s| s.borrow_mut().get_1d())
+}
+
+pub(crate) fn sample_2d() -> (f64, f64){
+    SAMPLER.with(|s| s.borrow_mut().get_2d())
+}
+
+fn mix_bits(mut v:u64) -> u64{
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(a:u64, b:u64) -> u64{
+    mix_bits(a ^ mix_bits(b.wrapping_add(0x9e3779b97f4a7c15)))
+}
+
+fn pixel_seed(x:u32, y:u32) -> u64{
+    hash(x as u64, y as u64)
+}
+
+//独立的随机数 每个维度都是 thread_rng
+#[derive(Clone)]
+pub(crate) struct RandomSampler;
+
+impl Sampler for RandomSampler{
+    fn start_pixel_sample(&mut self, _x: u32, _y: u32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        rand_f64()
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+//Laine-Karras 哈希置换 对倒序的位做 等价于 Owen 嵌套均匀打乱
+fn laine_karras_permutation(mut x:u32, seed:u32) -> u32{
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x:u32, seed:u32) -> u32{
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+//Sobol 的前两维 第 0 维是倒序的 index 第 1 维的方向数由多项式 x + 1 生成
+fn sobol_2d(index:u32) -> (u32, u32){
+    let mut i = index;
+    let mut v = 1u32 << 31;
+    let mut y = 0u32;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+fn to_unit(x:u32) -> f64{
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+//Owen 打乱的 Sobol 每个维度(或每对维度)用前两维 按维度打乱采样顺序和数值 维度之间不相关
+//一个像素里 2 的幂个采样时每个二维投影都是 (0,2) 点集
+fn owen_sobol_1d(index:u32, seed:u64, dim:u32) -> f64{
+    let h = hash(seed, dim as u64);
+    let i = nested_uniform_scramble(index, h as u32);
+    to_unit(nested_uniform_scramble(i.reverse_bits(), (h >> 32) as u32))
+}
+
+fn owen_sobol_2d(index:u32, seed:u64, dim:u32) -> (f64, f64){
+    let h = hash(seed, dim as u64);
+    let i = nested_uniform_scramble(index, h as u32);
+    let (x, y) = sobol_2d(i);
+    let h2 = mix_bits(h);
+    (to_unit(nested_uniform_scramble(x, (h >> 32) as u32)), to_unit(nested_uniform_scramble(y, h2 as u32)))
+}
+
+#[derive(Clone)]
+pub(crate) struct SobolSampler{
+    seed:u64,
+    index:u32,
+    dim:u32,
+}
+
+impl SobolSampler{
+    pub(crate) fn new() -> Self{
+        Self{ seed: 0, index: 0, dim: 0 }
+    }
+}
+
+impl Sampler for SobolSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.seed = pixel_seed(x, y);
+        self.index = index;
+        self.dim = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let v = owen_sobol_1d(self.index, self.seed, self.dim);
+        self.dim += 1;
+        v
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let v = owen_sobol_2d(self.index, self.seed, self.dim);
+        self.dim += 1;
+        v
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+//Halton 第 d 维是以第 d 个素数为底的倒序数 每一位按像素和维度做随机平移打乱
+//维度超过素数表时退回独立随机数
+#[derive(Clone)]
+pub(crate) struct HaltonSampler{
+    primes:Arc<Vec<u32>>,
+    seed:u64,
+    index:u32,
+    dim:usize,
+}
+
+impl HaltonSampler{
+    pub(crate) fn form(dimensions:usize) -> Self{
+        let mut primes = vec![];
+        let mut n = 2u32;
+        while primes.len() < dimensions {
+            if primes.iter().take_while(|&&p| p * p <= n).all(|&p| n % p != 0) {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        Self{ primes: Arc::new(primes), seed: 0, index: 0, dim: 0 }
+    }
+
+    //每一位数字加上一个由 (种子, 位数) 决定的偏移 模底数 index 用完以后的 0 也要平移
+    fn scrambled_radical_inverse(base:u32, mut index:u32, seed:u64) -> f64{
+        let inv_base = 1.0 / base as f64;
+        let mut inv_base_n = inv_base;
+        let mut result = 0.0;
+        let mut digit_n = 0u64;
+        while inv_base_n > f64::EPSILON * 0.25 {
+            let shift = (hash(seed, digit_n) % base as u64) as u32;
+            let digit = (index % base + shift) % base;
+            result += digit as f64 * inv_base_n;
+            index /= base;
+            inv_base_n *= inv_base;
+            digit_n += 1;
+        }
+        result.min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for HaltonSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.seed = pixel_seed(x, y);
+        self.index = index;
+        self.dim = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dim = self.dim;
+        self.dim += 1;
+        match self.primes.get(dim) {
+            None => { rand_f64() }
+            Some(&base) => { Self::scrambled_radical_inverse(base, self.index, hash(self.seed, dim as u64)) }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+//所有像素共用同一组 Sobol 点 每个像素按蓝噪声贴图平移(模 1)
+//相邻像素的误差不相关 画面上剩下的噪点是高频的 看起来更细
+#[derive(Clone)]
+pub(crate) struct BlueNoiseSampler{
+    mask:Arc<Vec<f64>>,
+    size:u32,
+    x:u32,
+    y:u32,
+    index:u32,
+    dim:u32,
+}
+
+impl BlueNoiseSampler{
+    pub(crate) fn form(mask:Arc<Vec<f64>>, size:u32) -> Self{
+        Self{ mask, size, x: 0, y: 0, index: 0, dim: 0 }
+    }
+
+    //每个维度把贴图错开一个随机的位置 维度之间不相关
+    fn offset(&self, dim:u32, component:u64) -> f64{
+        let h = hash(dim as u64, component);
+        let x = (self.x + (h as u32 % self.size)) % self.size;
+        let y = (self.y + ((h >> 32) as u32 % self.size)) % self.size;
+        self.mask[(y * self.size + x) as usize]
+    }
+
+    fn rotate(v:f64, offset:f64) -> f64{
+        let r = v + offset;
+        (if r >= 1.0 { r - 1.0 } else { r }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for BlueNoiseSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x % self.size;
+        self.y = y % self.size;
+        self.index = index;
+        self.dim = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let v = owen_sobol_1d(self.index, 0, self.dim);
+        let r = Self::rotate(v, self.offset(self.dim, 0));
+        self.dim += 1;
+        r
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (a, b) = owen_sobol_2d(self.index, 0, self.dim);
+        let r = (Self::rotate(a, self.offset(self.dim, 0)), Self::rotate(b, self.offset(self.dim, 1)));
+        self.dim += 1;
+        r
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+//void-and-cluster 生成 size x size 可平铺的蓝噪声阈值图 值是 (排名 + 0.5) / 像素数
+//能量是周围点的高斯加权和 (环绕计算) 最密的点能量最大 最大的空洞能量最小
+pub(crate) fn blue_noise_mask(size:usize) -> Vec<f64>{
+    let n = size * size;
+    let sigma = 1.9;
+    let mut kernel = vec![0.0;n];
+    for dy in 0..size {
+        for dx in 0..size {
+            let x = dx.min(size - dx) as f64;
+            let y = dy.min(size - dy) as f64;
+            kernel[dy * size + dx] = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+    let splat = |energy:&mut Vec<f64>, i:usize, sign:f64| {
+        let (ix, iy) = (i % size, i / size);
+        for y in 0..size {
+            let dy = (y + size - iy) % size;
+            for x in 0..size {
+                let dx = (x + size - ix) % size;
+                energy[y * size + x] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+    //在点(或空位)里找能量最大(或最小)的位置
+    let tightest_cluster = |pattern:&Vec<bool>, energy:&Vec<f64>| {
+        (0..n).filter(|&i| pattern[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern:&Vec<bool>, energy:&Vec<f64>| {
+        (0..n).filter(|&i| !pattern[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    //初始随机放 10% 的点 再把最密的点挪到最大的空洞里 直到挪回原处
+    let mut pattern = vec![false;n];
+    let mut energy = vec![0.0;n];
+    let ones = (n / 10).max(1);
+    let mut placed = 0;
+    while placed < ones {
+        let i = rand_i3_range(0, n as i32) as usize;
+        if !pattern[i] {
+            pattern[i] = true;
+            splat(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize;n];
+    //初始的点按从密到疏拿掉 排名从后往前
+    let mut p = pattern.clone();
+    let mut e = energy.clone();
+    for r in (0..ones).rev() {
+        let cluster = tightest_cluster(&p, &e);
+        rank[cluster] = r;
+        p[cluster] = false;
+        splat(&mut e, cluster, -1.0);
+    }
+    //剩下的空位按从大到小填上
+    for r in ones..n {
+        let void = largest_void(&pattern, &energy);
+        rank[void] = r;
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+    }
+    rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //n 个采样在 [0, 1) 的 n 等分里每格正好一个
+    fn one_per_stratum(values:&[f64]) -> bool{
+        let n = values.len();
+        let mut hit = vec![false;n];
+        for &v in values {
+            let k = (v * n as f64) as usize;
+            if hit[k] {
+                return false;
+            }
+            hit[k] = true;
+        }
+        true
+    }
+
+    #[test]
+    fn sobol_pixel_samples_are_stratified(){
+        let mut sampler = SobolSampler::new();
+        for &(x, y) in &[(0, 0), (17, 5), (300, 211)] {
+            let mut points = vec![];
+            let mut ys = vec![];
+            let mut ts = vec![];
+            for i in 0..16 {
+                sampler.start_pixel_sample(x, y, i);
+                let (a, b) = sampler.get_2d();
+                points.push((a, b));
+                ys.push(b);
+                ts.push(sampler.get_1d());
+            }
+            //(0,2) 点集: 面积 1/16 的每种长方形格子里都正好一个
+            for log_w in 0..=4 {
+                let (cols, rows) = (1usize << log_w, 1usize << (4 - log_w));
+                let mut hit = [false;16];
+                for &(a, b) in &points {
+                    let k = (b * rows as f64) as usize * cols + (a * cols as f64) as usize;
+                    assert!(!hit[k]);
+                    hit[k] = true;
+                }
+            }
+            assert!(one_per_stratum(&ys));
+            assert!(one_per_stratum(&ts));
+        }
+    }
+
+    #[test]
+    fn halton_dimensions_are_stratified_in_their_base(){
+        let mut sampler = HaltonSampler::form(3);
+        //第 0 维底数 2 第 1 维底数 3 第 2 维底数 5
+        for &(dim, n) in &[(0usize, 16u32), (1, 27), (2, 25)] {
+            let values:Vec<f64> = (0..n).map(|i| {
+                sampler.start_pixel_sample(3, 9, i);
+                for _ in 0..dim {
+                    sampler.get_1d();
+                }
+                sampler.get_1d()
+            }).collect();
+            assert!(one_per_stratum(&values));
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_uses_every_rank_once(){
+        let mask = blue_noise_mask(8);
+        assert!(one_per_stratum(&mask));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::hit::{Hittable, HitRecorder};
+use crate::shape::AABB;
+use crate::common::Axis;
+use crate::material::Materials;
+use crate::point3;
+use std::f64::consts::PI;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+//离表面多近算击中 按包围盒对角线的比例算 物体放大缩小后精度跟着变 差分求梯度也用这个步长
+const SDF_RELATIVE_EPSILON:f64 = 4e-5;
+const SDF_MAX_STEPS:usize = 512;
+
+//有向距离场 外面为正 里面为负
+pub(crate) trait Sdf:Send + Sync + Debug {
+    fn distance(&self, p:Point3) -> f64;
+    //距离场的 Lipschitz 常数 步长按它缩小 保证不会穿过表面
+    fn lipschitz(&self) -> f64 {
+        1.0
+    }
+}
+
+//用户用闭包自己定义的距离场
+pub(crate) struct FnSdf{
+    f:Arc<dyn Fn(Point3) -> f64 + Send + Sync>,
+    lipschitz:f64,
+}
+
+impl FnSdf{
+    pub(crate) fn form(f:Arc<dyn Fn(Point3) -> f64 + Send + Sync>, lipschitz:f64) -> Self{
+        Self{ f, lipschitz }
+    }
+}
+
+impl Debug for FnSdf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"FnSdf(lipschitz: {})",self.lipschitz)
+    }
+}
+
+impl Sdf for FnSdf{
+    fn distance(&self, p: Point3) -> f64 {
+        (self.f)(p)
+    }
+    fn lipschitz(&self) -> f64 {
+        self.lipschitz
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SdfSphere{
+    center:Point3,
+    radius:f64,
+}
+
+impl SdfSphere{
+    pub(crate) fn form(center:Point3, radius:f64) -> Self{
+        Self{ center, radius }
+    }
+}
+
+impl Sdf for SdfSphere{
+    fn distance(&self, p: Point3) -> f64 {
+        (p - self.center).length() - self.radius
+    }
+}
+
+//圆角方块 half 为不算圆角时的半边长
+#[derive(Debug)]
+pub(crate) struct RoundedBox{
+    center:Point3,
+    half:Vec3,
+    radius:f64,
+}
+
+impl RoundedBox{
+    pub(crate) fn form(center:Point3, half:Vec3, radius:f64) -> Self{
+        Self{ center, half, radius }
+    }
+}
+
+impl Sdf for RoundedBox{
+    fn distance(&self, p: Point3) -> f64 {
+        let d = p - self.center;
+        let q = point3!(d.x.abs() - self.half.x, d.y.abs() - self.half.y, d.z.abs() - self.half.z);
+        let outside = Vec3::max(q, Vec3::new()).length();
+        let inside = f64::min(f64::max(q.x, f64::max(q.y, q.z)), 0.0);
+        outside + inside - self.radius
+    }
+}
+
+//胶囊 线段 a b 加上半径
+#[derive(Debug)]
+pub(crate) struct Capsule{
+    a:Point3,
+    b:Point3,
+    radius:f64,
+}
+
+impl Capsule{
+    pub(crate) fn form(a:Point3, b:Point3, radius:f64) -> Self{
+        Self{ a, b, radius }
+    }
+}
+
+impl Sdf for Capsule{
+    fn distance(&self, p: Point3) -> f64 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (Vec3::dot(pa, ba) / Vec3::dot(ba, ba)).clamp(0.0, 1.0);
+        (pa - ba * h).length() - self.radius
+    }
+}
+
+//平滑并集 k 越大过渡越圆滑
+#[derive(Debug)]
+pub(crate) struct SmoothUnion{
+    a:Arc<dyn Sdf>,
+    b:Arc<dyn Sdf>,
+    k:f64,
+}
+
+impl SmoothUnion{
+    pub(crate) fn form(a:Arc<dyn Sdf>, b:Arc<dyn Sdf>, k:f64) -> Self{
+        Self{ a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion{
+    fn distance(&self, p: Point3) -> f64 {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+    fn lipschitz(&self) -> f64 {
+        f64::max(self.a.lipschitz(), self.b.lipschitz())
+    }
+}
+
+//Mandelbulb 分形 距离是估计值 scale 为整体缩放
+#[derive(Debug)]
+pub(crate) struct Mandelbulb{
+    center:Point3,
+    scale:f64,
+    power:f64,
+    iterations:usize,
+}
+
+impl Mandelbulb{
+    pub(crate) fn form(center:Point3, scale:f64, power:f64, iterations:usize) -> Self{
+        Self{ center, scale, power, iterations }
+    }
+}
+
+impl Sdf for Mandelbulb{
+    fn distance(&self, p: Point3) -> f64 {
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _i in 0..self.iterations{
+            if r > 2.0 || r == 0.0 {
+                break;
+            }
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z = point3!(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + c;
+            r = z.length();
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+    //估计值在表面附近偏大 步子放小一点
+    fn lipschitz(&self) -> f64 {
+        2.0
+    }
+}
+
+//光线步进求交的距离场物体 包围盒由调用方给出 步进只在包围盒内进行
+pub(crate) struct SdfShape{
+    sdf:Arc<dyn Sdf>,
+    bbox:AABB,
+    mp:Option<Arc<dyn Materials>>,
+    epsilon:f64,
+}
+
+impl SdfShape{
+    pub(crate) fn form(sdf:Arc<dyn Sdf>, bbox:AABB, ma:Arc<dyn Materials>) -> Self{
+        //离原点很远的物体 阈值至少要比坐标的舍入误差大
+        let magnitude = Vec3::max(bbox.minimum.abs(), bbox.maximum.abs());
+        let magnitude = magnitude.x.max(magnitude.y).max(magnitude.z);
+        let epsilon = (SDF_RELATIVE_EPSILON * (bbox.maximum - bbox.minimum).length()).max(magnitude * 64.0 * f64::EPSILON);
+        Self{
+            sdf,
+            bbox,
+            mp: Some(ma),
+            epsilon,
+        }
+    }
+
+    //四面体差分估计梯度 比中心差分少算两次距离
+    fn normal(&self, p:Point3) -> Vec3{
+        let h = self.epsilon;
+        let k = [point3!(1, -1, -1), point3!(-1, -1, 1), point3!(-1, 1, -1), point3!(1, 1, 1)];
+        let mut n = Vec3::new();
+        for d in k.iter(){
+            n += *d * self.sdf.distance(p + *d * h);
+        }
+        n.unit_vector()
+    }
+}
+
+impl Debug for SdfShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"SdfShape({:?})",self.sdf)
+    }
+}
+
+impl Hittable for SdfShape{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let (t0, t1) = match self.bbox.interval(ray, t_min, t_max) {
+            None => { return false }
+            Some(range) => { range }
+        };
+        //距离场按世界空间的长度算 方向不是单位向量时要换算成 t
+        let len = ray.direction().length();
+        let step_scale = 1.0 / (self.sdf.lipschitz() * len);
+        let mut t = t0;
+        //起点就在表面上(比如折射后的光线) 先离开表面一点 不然会马上击中自己
+        if self.sdf.distance(ray.at(t)).abs() < self.epsilon {
+            t += 10.0 * self.epsilon / len;
+        }
+        //起点在物体内部时 距离为负 按绝对值步进找离开的点
+        let sign = if self.sdf.distance(ray.at(t)) < 0.0 { -1.0 } else { 1.0 };
+        for _i in 0..SDF_MAX_STEPS{
+            if t > t1 {
+                return false;
+            }
+            let d = self.sdf.distance(ray.at(t)) * sign;
+            if d < self.epsilon {
+                let p = ray.at(t);
+                let outward = self.normal(p);
+                rec.t = t;
+                rec.p = Some(p);
+                //以包围盒中心做球面映射的 uv
+                let center = (self.bbox.minimum + self.bbox.maximum) / 2.0;
+                let dir = (p - center).unit_vector();
+                rec.u = (dir.z.atan2(-dir.x) + PI) / (2.0 * PI);
+                rec.v = (-dir.y).acos() / PI;
+                rec.material = self.mp.clone();
+                rec.set_face_normal(ray, outward);
+                //停下来的点离表面不超过击中阈值 误差比舍入大得多
+                rec.p_error = Some(Vec3::form(self.epsilon, self.epsilon, self.epsilon));
+                return true;
+            }
+            t += d * step_scale;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call((self.bbox.minimum + self.bbox.maximum) / 2.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
@@ -2,51 +2,60 @@ use std::sync::Arc;
 use crate::hit::{HitRecorder, Hittable};
 use crate::common::{rand_f64, rand_range_f64};
 use crate::{point3,vec3};
-use crate::bvh::BvhNode;
 use crate::Color;
 use crate::hittable_list::HittableList;
-use crate::shape::{Sphere, XyRect, YzRect, XzRect, MBox, YRotate, Translate};
-use crate::material::{Lambertian, Dielectric, Metal, Materials, DiffuseLight};
-use crate::texture::{CheckerTexture, NoiseTexture};
+use crate::tlas::Tlas;
+use crate::shape::{Sphere, MovingSphere, Triangle, XyRect, YzRect, XzRect, MBox, YRotate, Translate, Quad, Disk, Cylinder, Cone, Torus, Plane};
+use crate::material::{Lambertian, Dielectric, Metal, Materials, DiffuseLight, Hair};
+use crate::texture::{SolidColor, CheckerTexture, NoiseTexture, UvCheckerTexture, FbmTexture, WorleyTexture, WorleyMode, WoodTexture, GradientTexture, GradientInput, MixTexture, MultiplyTexture, RemapTexture, Texture};
+use crate::common::Axis;
+use crate::normal_map::{NormalMap, BumpMap};
 use crate::ray::{Point3, Ray};
 use crate::stl_reader::StlReader;
+use crate::obj_reader::ObjReader;
+use crate::mesh::{MeshTransform, MeshFit, Subdivision};
+use crate::precision::Precision;
+use crate::vec3::Vec3;
+use crate::transform::{Mat4, AnimatedTransform, Keyframe};
+use crate::csg::Csg;
+use crate::sdf::{SdfShape, FnSdf, SdfSphere, RoundedBox, Capsule, SmoothUnion, Mandelbulb};
+use crate::shape::AABB;
+use crate::curve::{Curve, CurveType};
 
 
 pub(crate) struct SencesManger{
     light:Option<Arc<dyn Hittable>>,
-    objs:Vec<Arc<dyn Hittable>>,
-    finally_objs:Option<Arc<HittableList>>,
+    tlas:Tlas,
 }
 
 impl SencesManger{
     pub(crate) fn new() -> Arc<Self>{
         Arc::new(Self{
             light: None,
-            objs: vec![],
-            finally_objs: None
+            tlas: Tlas::new(),
         })
     }
-    fn form(light:Option<Arc<dyn Hittable>>,objs:Vec<Arc<dyn Hittable>>) -> Arc<Self>{
-        let mut s = Self{
+    //每个物体作为一个不带变换的实例放进顶层
+    pub(crate) fn form(light:Option<Arc<dyn Hittable>>,objs:Vec<Arc<dyn Hittable>>) -> Arc<Self>{
+        let mut tlas = Tlas::new();
+        for obj in objs{
+            tlas.add(obj);
+        }
+        Self::form_tlas(light, tlas)
+    }
+    pub(crate) fn form_tlas(light:Option<Arc<dyn Hittable>>,mut tlas:Tlas) -> Arc<Self>{
+        tlas.rebuild();
+        Arc::new(Self{
             light,
-            objs,
-            finally_objs: None,
-        };
-        s.build_bvh();
-        Arc::new(s)
+            tlas,
+        })
     }
     pub(crate) fn hit(&self, ray:Ray, min:f64, max:f64, rec:&mut HitRecorder) -> bool{
-        return self.finally_objs.clone().unwrap().hit(ray,min,max,rec)
+        self.tlas.hit(ray,min,max,rec)
     }
     pub(crate) fn light(&self) -> Option<Arc<dyn Hittable>> {
         return self.light.clone()
     }
-    pub(crate) fn build_bvh(&mut self){
-        let bvh_node = BvhNode::form(self.objs.as_mut_slice(),0.0001,f64::MAX);
-        let mut hitable_list = HittableList::new();
-        hitable_list.add(Arc::new(bvh_node.unwrap()));
-        self.finally_objs = Some(Arc::new(hitable_list));
-    }
 }
 
 pub(crate) fn random_scene() -> Vec<Arc<dyn Hittable>> {
@@ -109,6 +118,50 @@ pub(crate) fn two_perlin_spheres() -> Vec<Arc<dyn Hittable>> {
 }
 
 
+pub(crate) fn procedural_textures() -> Vec<Arc<dyn Hittable>> {
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let ground = Arc::new(UvCheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9), 2000.0, 1000.0));
+    objs.push(Arc::new(Sphere::form(point3!(0.0,-1000.0,0.0), 1000.0, Arc::new(Lambertian::form(ground)))));
+
+    let wood = Arc::new(WoodTexture::form_color(Color::form(0.75, 0.55, 0.3), Color::form(0.4, 0.22, 0.08), 8.0, 0.6));
+    let knots = Arc::new(WorleyTexture::form(1.5, WorleyMode::F1));
+    let knotted = Arc::new(MultiplyTexture::form(wood, Arc::new(RemapTexture::form(knots, 0.0, 0.3, Color::form(0.5, 0.4, 0.3), Color::form(1.0, 1.0, 1.0)))));
+    let grain = Arc::new(RemapTexture::form(Arc::new(FbmTexture::form(6.0, 4)), 0.3, 0.7, Color::form(0.4, 0.4, 1.0), Color::form(0.6, 0.6, 1.0)));
+    let wood_ball = Arc::new(Sphere::form(point3!(-4.5, 1.0, 0.0), 1.0, Arc::new(Lambertian::form(knotted))));
+    objs.push(Arc::new(NormalMap::form(wood_ball, grain)));
+
+    let cracks:Arc<dyn Texture> = Arc::new(WorleyTexture::form(3.0, WorleyMode::F2MinusF1));
+    let pebbles:Arc<dyn Texture> = Arc::new(WorleyTexture::form(3.0, WorleyMode::F2));
+    let cells:Arc<dyn Texture> = Arc::new(MixTexture::form_constant(cracks, pebbles, 0.3));
+    let stone = Arc::new(RemapTexture::form(cells.clone(), 0.0, 0.6, Color::form(0.1, 0.1, 0.15), Color::form(0.8, 0.75, 0.7)));
+    let stone_ball = Arc::new(Sphere::form(point3!(-1.5, 1.0, 0.0), 1.0, Arc::new(Lambertian::form(stone))));
+    objs.push(Arc::new(BumpMap::form(stone_ball, cells, 0.05)));
+
+    let clouds:Arc<dyn Texture> = Arc::new(FbmTexture::form(2.0, 6));
+    let sky = Arc::new(GradientTexture::form(vec![
+        (0.0, Color::form(0.05, 0.1, 0.4)),
+        (0.5, Color::form(0.3, 0.6, 0.9)),
+        (1.0, Color::form(1.0, 1.0, 1.0))], GradientInput::V));
+    let cloudy_sky = Arc::new(MixTexture::form(sky, Arc::new(SolidColor::form(1.0, 1.0, 1.0)), clouds));
+    objs.push(Arc::new(Sphere::form(point3!(1.5, 1.0, 0.0), 1.0, Arc::new(Lambertian::form(cloudy_sky)))));
+
+    let heat = Arc::new(GradientTexture::form(vec![
+        (0.0, Color::form(0.1, 0.0, 0.0)),
+        (0.6, Color::form(0.9, 0.3, 0.0)),
+        (1.0, Color::form(1.0, 0.9, 0.3))], GradientInput::World(Axis::Y, 0.0, 2.0)));
+    let shade = Arc::new(GradientTexture::form(vec![
+        (0.0, Color::form(1.0, 1.0, 1.0)),
+        (0.5, Color::form(0.4, 0.4, 0.4)),
+        (1.0, Color::form(1.0, 1.0, 1.0))], GradientInput::U));
+    let mixed = Arc::new(MixTexture::form_constant(heat.clone(), Arc::new(MultiplyTexture::form(heat, shade)), 0.7));
+    objs.push(Arc::new(Sphere::form(point3!(4.5, 1.0, 0.0), 1.0, Arc::new(Lambertian::form(mixed)))));
+
+    let tiles = Arc::new(UvCheckerTexture::form_color(Color::form(0.8, 0.1, 0.1), Color::form(0.9, 0.9, 0.9), 8.0, 8.0));
+    objs.push(Arc::new(Triangle::form_by_center(point3!(0.0, 3.5, -4.0), 3.0, 4.0, Arc::new(Lambertian::form(tiles)))));
+    objs
+}
+
+
 pub(crate) fn simple_light() -> Vec<Arc<dyn Hittable>>{
     let mut objs:Vec<Arc<dyn Hittable>> = vec![];
     let pertext = Arc::new(NoiseTexture::form(4.0));
@@ -121,6 +174,288 @@ pub(crate) fn simple_light() -> Vec<Arc<dyn Hittable>>{
 
 }
 
+pub(crate) fn obj_scene() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let ground = Arc::new(UvCheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9), 2000.0, 1000.0));
+    objs.push(Arc::new(Sphere::form(point3!(0.0,-1000.0,0.0), 1000.0, Arc::new(Lambertian::form(ground)))));
+
+    let reader = match ObjReader::new_obj_reader("crate.obj".to_string(), Arc::new(Lambertian::form_color(0.73, 0.73, 0.73))) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
+    for w in reader.warnings(){
+        eprintln!("{}", w);
+    }
+    objs.push(Arc::new(reader.read_all_shape_info(&MeshTransform::new()).unwrap()));
+    let gem = reader.read_group("gem", &MeshTransform{
+        translate: vec3!(-4, 1, 0),
+        fit: MeshFit::CenterOnOrigin,
+        ..MeshTransform::new()
+    }).unwrap();
+    objs.push(Arc::new(gem));
+
+    let mut stl_reader = match StlReader::new_stl_reader("cat.stl".to_string()) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
+    let cat = stl_reader.raed_all_shape_info(Arc::new(Metal::form(0.95, 0.75, 0.34, 0.3)), &MeshTransform{
+        rotate: vec3!(-90, 0, 0),
+        fit: MeshFit::FitToBox(point3!(-3, 0, -5), point3!(3, 3, -3)),
+        ..MeshTransform::new()
+    }).unwrap();
+    objs.push(Arc::new(cat));
+
+    let light = Arc::new(DiffuseLight::form(Color::form(4.0, 4.0, 4.0)));
+    let light_ref = Arc::new(XzRect::form(-2.0, 2.0, -2.0, 2.0, 6.0, light));
+    objs.push(light_ref.clone());
+    SencesManger::form(Some(light_ref),objs)
+}
+
+//同一棵猫的网格 BVH 用不同的矩阵摆放很多次 三角形只存一份
+pub(crate) fn instanced_cats() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let ground = Arc::new(UvCheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9), 2000.0, 1000.0));
+    objs.push(Arc::new(Sphere::form(point3!(0.0,-1000.0,0.0), 1000.0, Arc::new(Lambertian::form(ground)))));
+
+    let mut stl_reader = match StlReader::new_stl_reader("cat.stl".to_string()) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
+    //猫的 BVH 只建一次 100 个实例共享
+    let cat:Arc<dyn Hittable> = Arc::new(stl_reader.raed_all_shape_info(Arc::new(Lambertian::form_color(0.73, 0.73, 0.73)), &MeshTransform{
+        rotate: vec3!(-90, 0, 0),
+        fit: MeshFit::FitToBox(point3!(-0.5, 0, -0.5), point3!(0.5, 1, 0.5)),
+        ..MeshTransform::new()
+    }).unwrap());
+    let mut tlas = Tlas::new();
+    for obj in objs{
+        tlas.add(obj);
+    }
+    let mut cats = vec![];
+    for i in -5 .. 5{
+        for j in -5 .. 5{
+            let grid = Mat4::translate(vec3!(i as f64 * 1.5, 0, j as f64 * 1.5));
+            cats.push((tlas.add_instance(cat.clone(), grid), grid));
+        }
+    }
+    //只改实例的变换 form_tlas 里重建顶层 底层猫的 BVH 不用重建
+    for (index, grid) in cats{
+        let scale = rand_range_f64(0.6, 1.2);
+        let placement = grid *
+            Mat4::rotate(vec3!(0, 1, 0), rand_range_f64(0.0, 360.0)) *
+            Mat4::shear(rand_range_f64(-0.3, 0.3), 0.0, 0.0, 0.0, 0.0, 0.0) *
+            Mat4::scale(vec3!(scale, scale * rand_range_f64(0.8, 1.3), scale));
+        tlas.set_transform(index, placement);
+    }
+    let light = Arc::new(DiffuseLight::form(Color::form(6.0, 6.0, 6.0)));
+    let light_ref:Arc<dyn Hittable> = Arc::new(XzRect::form(-4.0, 4.0, -4.0, 4.0, 10.0, light));
+    tlas.add(light_ref.clone());
+    SencesManger::form_tlas(Some(light_ref),tlas)
+}
+
+//同一只猫: 原始网格 / Loop 细分 / Catmull-Clark 细分加噪声位移
+pub(crate) fn subdivided_cats() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(CheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9)));
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form(checker)))));
+
+    let mut stl_reader = match StlReader::new_stl_reader("cat.stl".to_string()) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
+    let noise:Arc<dyn Texture> = Arc::new(FbmTexture::form(40.0, 4));
+    let settings = [
+        (-2.2, Subdivision::None, None),
+        (0.0, Subdivision::Loop(1), None),
+        (2.2, Subdivision::CatmullClark(1), Some((noise, 0.02))),
+    ];
+    for (x, subdivision, displacement) in settings{
+        let cat = stl_reader.raed_all_shape_info(Arc::new(Lambertian::form_color(0.73, 0.73, 0.73)), &MeshTransform{
+            rotate: vec3!(-90, 0, 0),
+            fit: MeshFit::FitToBox(point3!(x - 1.0, 0, -1), point3!(x + 1.0, 2, 1)),
+            subdivision,
+            displacement,
+            //细分之后顶点很多 用 f32 存省一半内存
+            precision: Precision::F32,
+            ..MeshTransform::new()
+        });
+        objs.push(Arc::new(cat.unwrap()));
+    }
+    let light = Arc::new(DiffuseLight::form(Color::form(6.0, 6.0, 6.0)));
+    let light_ref = Arc::new(XzRect::form(-3.0, 3.0, -3.0, 3.0, 8.0, light));
+    objs.push(light_ref.clone());
+    SencesManger::form(Some(light_ref),objs)
+}
+
+//长毛的猫站在草地上 几万根发丝和草都放进 BVH
+pub(crate) fn furry_cat() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form_color(0.3, 0.2, 0.1)))));
+
+    //草叶: 扁平的曲线 从根部到叶尖变细 顶端随机弯向一边
+    let grass:Arc<dyn Materials> = Arc::new(Lambertian::form_color(0.15, 0.45, 0.1));
+    for _i in 0..20000{
+        let root = point3!(rand_range_f64(-6.0, 6.0), 0, rand_range_f64(-6.0, 3.0));
+        let height = rand_range_f64(0.15, 0.4);
+        let bend = vec3!(rand_range_f64(-1.0, 1.0), 0, rand_range_f64(-1.0, 1.0)) * (height * 0.4);
+        let cp = [root, root + vec3!(0, height / 3.0, 0), root + vec3!(0, height * 2.0 / 3.0, 0) + bend * 0.5, root + vec3!(0, height, 0) + bend];
+        objs.push(Arc::new(Curve::form(cp, 0.02, 0.002, CurveType::Flat, grass.clone())));
+    }
+
+    let mut stl_reader = match StlReader::new_stl_reader("cat.stl".to_string()) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
+    let mesh = stl_reader.read_mesh(Arc::new(Lambertian::form_color(0.35, 0.2, 0.08)), &MeshTransform{
+        rotate: vec3!(-90, 0, 0),
+        fit: MeshFit::FitToBox(point3!(-1, 0, -1), point3!(1, 2, 1)),
+        ..MeshTransform::new()
+    });
+    //毛沿法线长出来 末端受重力往下垂
+    let fur:Arc<dyn Materials> = Arc::new(Hair::form_color(Color::form(0.8, 0.5, 0.25), 0.3, 0.3));
+    for (p, n) in mesh.sample_surface(30000){
+        let len = rand_range_f64(0.04, 0.08);
+        let droop = vec3!(0, -len * 0.5, 0) + Vec3::random_in_unit_sphere() * (len * 0.2);
+        let cp = [p, p + n * (len / 3.0), p + n * (len * 2.0 / 3.0) + droop * 0.4, p + n * len + droop];
+        objs.push(Arc::new(Curve::form(cp, 0.006, 0.001, CurveType::Cylinder, fur.clone())));
+    }
+    if let Some(cat) = mesh.build_bvh() {
+        objs.push(Arc::new(cat));
+    }
+    SencesManger::form(None,objs)
+}
+
+//快门时间 [0,1] 内运动的物体
+pub(crate) fn motion_blur() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(CheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9)));
+    objs.push(Arc::new(Sphere::form(point3!(0.0,-1000.0,0.0), 1000.0, Arc::new(Lambertian::form(checker)))));
+    for i in -6 .. 6{
+        let albedo = Color::random() * Color::random();
+        let center0 = point3!(i as f64 + 0.9 * rand_f64(), 0.2, 2.0 + rand_f64());
+        let center1 = center0 + vec3!(0, rand_range_f64(0.0, 0.5), 0);
+        objs.push(Arc::new(MovingSphere::form(center0, center1, 0.0, 1.0, 0.2,
+                                              Arc::new(Lambertian::form_color(albedo.x, albedo.y, albedo.z)))));
+    }
+
+    let cube:Arc<dyn Hittable> = Arc::new(MBox::form(point3!(-0.5, -0.5, -0.5), point3!(0.5, 0.5, 0.5),
+                                                     Arc::new(Lambertian::form_color(0.65, 0.05, 0.05))));
+    let one = vec3!(1, 1, 1);
+    objs.push(Arc::new(AnimatedTransform::form(cube, vec![
+        Keyframe::form(0.0, vec3!(-2, 1, 0), vec3!(0, 0, 0), one),
+        Keyframe::form(0.5, vec3!(-2, 1.3, 0), vec3!(0, 45, 20), one * 1.2),
+        Keyframe::form(1.0, vec3!(-2, 1, 0), vec3!(0, 90, 0), one),
+    ])));
+    let ball:Arc<dyn Hittable> = Arc::new(Sphere::form(point3!(0, 0, 0), 1.0, Arc::new(Metal::form(0.7, 0.6, 0.5, 0.0))));
+    objs.push(Arc::new(AnimatedTransform::form_linear(ball,
+        Keyframe::form(0.0, vec3!(1.5, 1, 0), vec3!(0, 0, 0), one),
+        Keyframe::form(1.0, vec3!(2.5, 1, 0), vec3!(0, 0, 0), one))));
+    SencesManger::form(None,objs)
+}
+
+//景深和焦外光斑: 一排往远处排开的球 背景里有很多发光的小球
+pub(crate) fn bokeh() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(CheckerTexture::form_color(Color::form(0.2, 0.2, 0.2), Color::form(0.8, 0.8, 0.8)));
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form(checker)))));
+    for i in 0..6{
+        let z = 3.0 - i as f64 * 2.5;
+        let albedo = Color::random() * 0.5 + 0.4;
+        objs.push(Arc::new(Sphere::form(point3!(0.9 * (i % 2) as f64 - 0.45, 0.5, z), 0.5,
+                                        Arc::new(Lambertian::form_color(albedo.x, albedo.y, albedo.z)))));
+    }
+    for _i in 0..80{
+        let c = Color::random() * 0.6 + 0.4;
+        let light = Arc::new(DiffuseLight::form(c * 8.0));
+        objs.push(Arc::new(Sphere::form(point3!(rand_range_f64(-12.0, 12.0), rand_range_f64(0.5, 7.0), rand_range_f64(-40.0, -25.0)),
+                                        0.08, light)));
+    }
+    let light = Arc::new(DiffuseLight::form(Color::form(4.0, 4.0, 4.0)));
+    let light_ref = Arc::new(XzRect::form(-3.0, 3.0, -4.0, 4.0, 6.0, light));
+    objs.push(light_ref.clone());
+    SencesManger::form(Some(light_ref),objs)
+}
+
+pub(crate) fn primitives() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(UvCheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9), 1.0, 1.0));
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form(checker)))));
+
+    let uv = Arc::new(UvCheckerTexture::form_color(Color::form(0.8, 0.1, 0.1), Color::form(0.9, 0.9, 0.9), 8.0, 4.0));
+    objs.push(Arc::new(Cylinder::form(point3!(-3, 0, 0), vec3!(0, 1, 0), 0.7, 1.8, Arc::new(Lambertian::form(uv.clone())))));
+    objs.push(Arc::new(Cone::form(point3!(-1, 0, 0), vec3!(0, 1, 0), 0.7, 1.8, Arc::new(Metal::form(0.8, 0.6, 0.2, 0.2)))));
+    objs.push(Arc::new(Torus::form(point3!(1.2, 0.9, 0), vec3!(0, 1, 1), 0.7, 0.25, Arc::new(Lambertian::form(uv)))));
+    objs.push(Arc::new(Disk::form(point3!(3, 0.9, 0), vec3!(-1, 0, 1), 0.8, Arc::new(Dielectric::form(1.5)))));
+    objs.push(Arc::new(Quad::form(point3!(-4, 0, -3), vec3!(8, 0, 0), vec3!(0, 3, -0.5), Arc::new(Lambertian::form_color(0.2, 0.3, 0.8)))));
+
+    //圆盘做光源 可以直接采样
+    let light:Arc<dyn Hittable> = Arc::new(Disk::form(point3!(0, 5, 2), vec3!(0, -1, 0), 1.0,
+                                                      Arc::new(DiffuseLight::form(Color::form(6.0, 6.0, 6.0)))));
+    objs.push(light.clone());
+    SencesManger::form(Some(light),objs)
+}
+
+pub(crate) fn csg_scene() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(CheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9)));
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form(checker)))));
+
+    //方块挖掉一个球
+    let red = Arc::new(Lambertian::form_color(0.65, 0.05, 0.05));
+    let cube:Arc<dyn Hittable> = Arc::new(MBox::form(point3!(-3.5, 0, -0.75), point3!(-2, 1.5, 0.75), red));
+    let hole:Arc<dyn Hittable> = Arc::new(Sphere::form(point3!(-2.75, 0.75, 0.75), 0.6, Arc::new(Lambertian::form_color(0.9, 0.9, 0.2))));
+    objs.push(Arc::new(Csg::difference(cube, hole)));
+
+    //两个大球的交集是凸透镜
+    let glass = Arc::new(Dielectric::form(1.5));
+    let lens_a:Arc<dyn Hittable> = Arc::new(Sphere::form(point3!(0, 1.2, -1.6), 2.0, glass.clone()));
+    let lens_b:Arc<dyn Hittable> = Arc::new(Sphere::form(point3!(0, 1.2, 1.6), 2.0, glass.clone()));
+    objs.push(Arc::new(Csg::intersection(lens_a, lens_b)));
+
+    //圆柱和球的并集再挖掉一个竖着的圆柱 做成空心的玻璃
+    let body:Arc<dyn Hittable> = Arc::new(Cylinder::form(point3!(2.75, 0, 0), vec3!(0, 1, 0), 0.6, 1.2, glass.clone()));
+    let top:Arc<dyn Hittable> = Arc::new(Sphere::form(point3!(2.75, 1.2, 0), 0.6, glass.clone()));
+    let solid:Arc<dyn Hittable> = Arc::new(Csg::union(body, top));
+    let inner:Arc<dyn Hittable> = Arc::new(Cylinder::form(point3!(2.75, 0.2, 0), vec3!(0, 1, 0), 0.45, 2.0, glass));
+    objs.push(Arc::new(Csg::difference(solid, inner)));
+    objs.push(Arc::new(Sphere::form(point3!(2.75, 0.5, 0), 0.2, Arc::new(Metal::form(0.8, 0.6, 0.2, 0.1)))));
+    SencesManger::form(None,objs)
+}
+
+pub(crate) fn sdf_scene() -> Arc<SencesManger>{
+    let mut objs:Vec<Arc<dyn Hittable>> = vec![];
+    let checker = Arc::new(CheckerTexture::form_color(Color::form(0.2, 0.3, 0.1), Color::form(0.9, 0.9, 0.9)));
+    objs.push(Arc::new(Plane::form(point3!(0, 0, 0), vec3!(0, 1, 0), Arc::new(Lambertian::form(checker)))));
+
+    let rounded = Arc::new(RoundedBox::form(point3!(-3, 0.7, 0), vec3!(0.5, 0.5, 0.5), 0.2));
+    objs.push(Arc::new(SdfShape::form(rounded, AABB::form(point3!(-3.8, -0.1, -0.8), point3!(-2.2, 1.5, 0.8)),
+                                      Arc::new(Lambertian::form_color(0.65, 0.05, 0.05)))));
+
+    let capsule = Arc::new(Capsule::form(point3!(-1.6, 0.3, 0.5), point3!(-0.8, 1.6, -0.5), 0.3));
+    objs.push(Arc::new(SdfShape::form(capsule, AABB::form(point3!(-2, -0.1, -0.9), point3!(-0.4, 2, 0.9)),
+                                      Arc::new(Dielectric::form(1.5)))));
+
+    //两个球平滑地融在一起
+    let blob = Arc::new(SmoothUnion::form(Arc::new(SdfSphere::form(point3!(0.2, 0.6, 0), 0.5)),
+                                          Arc::new(SdfSphere::form(point3!(0.9, 1.1, 0), 0.4)), 0.4));
+    objs.push(Arc::new(SdfShape::form(blob, AABB::form(point3!(-0.4, 0, -0.7), point3!(1.5, 1.7, 0.7)),
+                                      Arc::new(Metal::form(0.8, 0.6, 0.2, 0.1)))));
+
+    let bulb = Arc::new(Mandelbulb::form(point3!(2.8, 1, 0), 0.8, 8.0, 12));
+    objs.push(Arc::new(SdfShape::form(bulb, AABB::form(point3!(1.8, 0, -1), point3!(3.8, 2, 1)),
+                                      Arc::new(Lambertian::form_color(0.2, 0.4, 0.8)))));
+
+    //自定义的距离场: 竖着的圆环
+    let ring = Arc::new(FnSdf::form(Arc::new(|p:Point3| {
+        let d = p - point3!(0, 1, -2.5);
+        let q = ((d.x * d.x + d.y * d.y).sqrt() - 0.8, d.z);
+        (q.0 * q.0 + q.1 * q.1).sqrt() - 0.15
+    }), 1.0));
+    objs.push(Arc::new(SdfShape::form(ring, AABB::form(point3!(-1, 0, -2.7), point3!(1, 2, -2.3)),
+                                      Arc::new(Lambertian::form_color(0.12, 0.45, 0.15)))));
+    SencesManger::form(None,objs)
+}
+
 pub(crate) fn cornell_box_light() -> Arc<dyn Hittable>{
     let light = Arc::new(DiffuseLight::form(Color::form(15.0, 15.0, 15.0)));
     let light_ref = Arc::new(XzRect::form(213.0, 343.0, 227.0, 332.0, 554.0, light));
@@ -163,12 +498,15 @@ pub(crate) fn cornell_box() -> Arc<SencesManger>{
     let sphere = Arc::new(Sphere::form(point3!(190.0,90.0,190.0),-100.0,glass));
     objs.push(sphere);
 
-    let mut stl_reader = StlReader::new_stl_reader("cat.stl".to_string());
+    let mut stl_reader = match StlReader::new_stl_reader("cat.stl".to_string()) {
+        Ok(reader) => { reader }
+        Err(e) => { panic!("{}", e) }
+    };
 
     let mut cat = HittableList::new();
 
 
-    let cat = stl_reader.raed_all_shape_info( gloden).unwrap();
+    let cat = stl_reader.raed_all_shape_info( gloden, &MeshTransform::form(5.0, vec3!(-80, 0, 0), Vec3::new())).unwrap();
 
     let rotat_box = YRotate::form(Arc::new(cat),180.0);
 
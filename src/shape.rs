@@ -4,9 +4,11 @@ use crate::hit::{Hittable, HitRecorder};
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 use crate::material::Materials;
-use crate::common::{cmp_f64, f64_near_zero, Axis, Tuple, degrees_to_radians, rand_range_f64};
+use crate::common::{cmp_f64, f64_near_zero, Axis, Tuple, degrees_to_radians};
+use crate::sampler::{sample_1d, sample_2d};
 use std::f64::consts::PI;
 use crate::{point3};
+use crate::precision::{Real, gamma, to_real3, from_real3};
 
 pub(crate) struct Sphere{
     center:Point3,
@@ -29,6 +31,27 @@ impl Sphere {
         let v = theta / PI;
         Tuple::UV(u,v)
     }
+    //get_sphere_uv 参数化下的 dp/du 和 dp/dv 两极处退化 随便取一组正交基
+    pub(crate) fn get_sphere_tangents(n:Vec3, radius:f64) -> (Vec3, Vec3){
+        let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+        if f64_near_zero(sin_theta) {
+            return Vec3::orthonormal_basis(n);
+        }
+        let dpdu = Vec3::form(n.z, 0.0, -n.x) * (2.0 * PI * radius);
+        let dpdv = Vec3::form(-n.x * n.y / sin_theta, sin_theta, -n.y * n.z / sin_theta) * (PI * radius);
+        (dpdu, dpdv)
+    }
+}
+
+//o + t*d 算出的点受 t 的误差影响 可能离球面较远 沿半径方向投影回球面上 半径为负(空心玻璃球)时按绝对值
+fn reproject_sphere(p:Point3, center:Point3, radius:f64) -> Point3{
+    let local = p - center;
+    center + local * (radius.abs() / local.length())
+}
+
+//投影后相对球心的坐标只有几次舍入 再加上和球心相加的舍入
+fn sphere_error(p:Point3, center:Point3) -> Vec3{
+    (p - center).abs() * gamma::<f64>(5) + p.abs() * gamma::<f64>(1)
 }
 
 impl Debug for Sphere {
@@ -57,7 +80,7 @@ impl Hittable for Sphere{
             }
         }
         rec.t = root;
-        rec.p = Some(ray.at(rec.t));
+        rec.p = Some(reproject_sphere(ray.at(rec.t), self.center, self.radius));
         rec.material = self.material.clone();
         let outward_normal = (rec.p.unwrap() - self.center) / self.radius;
         let uv =  Self::get_sphere_uv(outward_normal);
@@ -68,6 +91,9 @@ impl Hittable for Sphere{
             }
         }
         rec.set_face_normal(ray,outward_normal);
+        rec.p_error = Some(sphere_error(rec.p.unwrap(), self.center));
+        let (dpdu, dpdv) = Self::get_sphere_tangents(outward_normal, self.radius);
+        rec.set_tangents(dpdu, dpdv);
         return true;
     }
 
@@ -94,6 +120,101 @@ impl Hittable for Sphere{
 }
 
 
+//球心在 time0 到 time1 之间从 center0 匀速移动到 center1
+pub(crate) struct MovingSphere{
+    center0:Point3,
+    center1:Point3,
+    time0:f64,
+    time1:f64,
+    radius:f64,
+    pub(crate) material:Option<Arc<dyn Materials>>,
+}
+
+impl MovingSphere {
+    pub(crate) fn form(center0:Point3, center1:Point3, time0:f64, time1:f64, radius:f64, material: Arc<dyn Materials>) -> Self{
+        Self{
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material:Some(material)
+        }
+    }
+    //两个时刻相同时没有运动 停在 center0
+    pub(crate) fn center(&self, time:f64) -> Point3{
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        self.center0 + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl Debug for MovingSphere {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{:?} -> {:?}",self.center0,self.center1)
+    }
+}
+
+impl Hittable for MovingSphere{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let center = self.center(ray.time());
+        let oc = ray.origin() - center;
+        let a = ray.direction().length_squared();
+        let half_b =  Vec3::dot(oc,ray.direction());
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b -  a  * c;
+        if discriminant < 0.0 {
+            return false
+        }
+        let squared = discriminant.sqrt();
+        let mut root = (- half_b - squared) / a;
+        if root < t_min || t_max < root{
+            root = (- half_b + squared) / a;
+            if root < t_min || t_max < root{
+                return false;
+            }
+        }
+        rec.t = root;
+        rec.p = Some(reproject_sphere(ray.at(rec.t), center, self.radius));
+        rec.material = self.material.clone();
+        let outward_normal = (rec.p.unwrap() - center) / self.radius;
+        match Sphere::get_sphere_uv(outward_normal) {
+            Tuple::UV(u, v) => {
+                rec.u = u;
+                rec.v = v;
+            }
+        }
+        rec.set_face_normal(ray,outward_normal);
+        rec.p_error = Some(sphere_error(rec.p.unwrap(), center));
+        let (dpdu, dpdv) = Sphere::get_sphere_tangents(outward_normal, self.radius);
+        rec.set_tangents(dpdu, dpdv);
+        true
+    }
+
+    //包围盒覆盖整段运动
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = point3!(self.radius.abs(),self.radius.abs(),self.radius.abs());
+        Some(AABB::form(
+            Vec3::min(self.center0, self.center1) - r,
+            Vec3::max(self.center0, self.center1) + r,
+        ))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call((self.center0 + self.center1) / 2.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
+
+
 pub(crate) struct Triangle {
     pub(crate) p1:Point3,
     pub(crate) p2:Point3,
@@ -116,13 +237,7 @@ impl Triangle{
         let p1 = point3!( center.x,center.y + height / 2.0,center.z);
         let p2 = point3!(center.x - weight/2.0,center.y - height / 2.0,center.z);
         let p3 = point3!(center.x + weight/2.0,center.y - height / 2.0,center.z);
-        Self{
-            p1,
-            p2,
-            p3,
-            w:(p1+p2+p3) / 3.0,
-            material: Some(material)
-        }
+        Self::form(p1, p2, p3, material)
     }
 
 }
@@ -133,41 +248,181 @@ impl Debug for Triangle {
     }
 }
 
-//三角形的重心公式 然后使用 克莱姆法则求解
-impl Hittable for Triangle{
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
-        let e1 = self.p2 - self.p1;
-        let e2 = self.p3 - self.p1;
-        let t1 = ray.origin() - self.p1 ;
-        let p1 = Vec3::cross(ray.direction(),e2);
-        let p2 = Vec3::cross(t1,e1 );
-        let mut molecule = Vec3::dot(p1,t1);
-        let denominator = Vec3::dot(p1,e1);
-        if f64_near_zero(denominator) {
-            return false
+//三角形求交的结果 u v 分别是 p2 p3 的重心坐标 p_error 是击中点每个分量的误差上界
+pub(crate) struct TriangleHit{
+    pub(crate) t:f64,
+    pub(crate) u:f64,
+    pub(crate) v:f64,
+    pub(crate) p:Point3,
+    pub(crate) p_error:Vec3,
+}
+
+//a*d - b*c 结果为 0 时换成 f64 再算一次 避免 f32 下射线正好穿过边时漏掉
+fn edge_function<R:Real>(a:R, b:R, c:R, d:R) -> R{
+    let e = a * d - b * c;
+    if e == R::ZERO {
+        return R::from_f64(a.to_f64() * d.to_f64() - b.to_f64() * c.to_f64());
+    }
+    e
+}
+
+//水密的射线三角形求交(Woop 2013): 把射线变换到 +z 轴上 三角形投影到 xy 平面后用边函数判断
+//相邻三角形共用的边算出的边函数完全一样 射线不会从缝里漏过去
+//顶点按 R 的精度计算 最后用误差上界保守地判断 t > 0
+pub(crate) fn intersect_triangle<R:Real>(p:[[R;3];3], ray: Ray, t_min: f64, t_max: f64) -> Option<TriangleHit> {
+    let o:[R;3] = to_real3(ray.origin());
+    let d:[R;3] = to_real3(ray.direction());
+    //方向分量绝对值最大的轴作为 z 轴
+    let kz = if d[0].abs() > d[1].abs() {
+        if d[0].abs() > d[2].abs() { 0 } else { 2 }
+    } else if d[1].abs() > d[2].abs() { 1 } else { 2 };
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+    let d = [d[kx], d[ky], d[kz]];
+    let mut pt = p.map(|v| [v[kx] - o[kx], v[ky] - o[ky], v[kz] - o[kz]]);
+    //错切让射线方向变成 (0, 0, 1)
+    let sx = -d[0] / d[2];
+    let sy = -d[1] / d[2];
+    let sz = R::ONE / d[2];
+    for v in pt.iter_mut(){
+        v[0] = v[0] + sx * v[2];
+        v[1] = v[1] + sy * v[2];
+    }
+    let e0 = edge_function(pt[1][0], pt[1][1], pt[2][0], pt[2][1]);
+    let e1 = edge_function(pt[2][0], pt[2][1], pt[0][0], pt[0][1]);
+    let e2 = edge_function(pt[0][0], pt[0][1], pt[1][0], pt[1][1]);
+    if (e0 < R::ZERO || e1 < R::ZERO || e2 < R::ZERO) && (e0 > R::ZERO || e1 > R::ZERO || e2 > R::ZERO) {
+        return None;
+    }
+    let det = e0 + e1 + e2;
+    if det == R::ZERO {
+        return None;
+    }
+    //正好落在边上时只算给边的一侧: 按三角形的朝向把边转成同一个绕向 只要 "上边和左边"
+    //相邻三角形共用的边方向相反 只有一个三角形会要这条边
+    for (i, e) in [e0, e1, e2].iter().enumerate(){
+        if *e != R::ZERO {
+            continue;
         }
-        let u = molecule / denominator;
-        if u < 0.0 || u > 1.0{
-            return false;
+        let (from, to) = (pt[(i + 1) % 3], pt[(i + 2) % 3]);
+        let (mut dx, mut dy) = (to[0] - from[0], to[1] - from[1]);
+        if det < R::ZERO {
+            dx = -dx;
+            dy = -dy;
         }
-        molecule = Vec3::dot(p2,ray.direction());
-        let v = molecule / denominator;
-        if v < 0.0 || u + v > 1.0{
-            return false;
+        if !(dy > R::ZERO || (dy == R::ZERO && dx > R::ZERO)) {
+            return None;
         }
-        molecule = Vec3::dot(p2,e2);
-        let t = molecule / denominator;
-        if t < t_min || t_max < t{
-            return false;
+    }
+    for v in pt.iter_mut(){
+        v[2] = v[2] * sz;
+    }
+    //先不除以行列式 用乘法比较范围
+    let t_scaled = e0 * pt[0][2] + e1 * pt[1][2] + e2 * pt[2][2];
+    let t_max_r = R::from_f64(t_max);
+    if det < R::ZERO && (t_scaled >= R::ZERO || t_scaled < t_max_r * det) {
+        return None;
+    }
+    if det > R::ZERO && (t_scaled <= R::ZERO || t_scaled > t_max_r * det) {
+        return None;
+    }
+    let inv_det = R::ONE / det;
+    let b = [e0 * inv_det, e1 * inv_det, e2 * inv_det];
+    let t = t_scaled * inv_det;
+
+    //t 的误差上界 t 不比它大时可能其实在射线起点后面
+    let max_zt = pt[0][2].abs().max(pt[1][2].abs()).max(pt[2][2].abs());
+    let max_xt = pt[0][0].abs().max(pt[1][0].abs()).max(pt[2][0].abs());
+    let max_yt = pt[0][1].abs().max(pt[1][1].abs()).max(pt[2][1].abs());
+    let delta_z = gamma::<R>(3) * max_zt;
+    let delta_x = gamma::<R>(5) * (max_xt + max_zt);
+    let delta_y = gamma::<R>(5) * (max_yt + max_zt);
+    let two = R::from_f64(2.0);
+    let three = R::from_f64(3.0);
+    let delta_e = two * (gamma::<R>(2) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
+    let max_e = e0.abs().max(e1.abs()).max(e2.abs());
+    let delta_t = three * (gamma::<R>(3) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
+    if t <= delta_t || t.to_f64() < t_min {
+        return None;
+    }
+
+    //击中点用重心坐标插值 比 o + t*d 误差小
+    let mut hit_p = [R::ZERO; 3];
+    let mut abs_sum = [R::ZERO; 3];
+    for (k, bk) in b.iter().enumerate(){
+        for i in 0..3{
+            hit_p[i] = hit_p[i] + *bk * p[k][i];
+            abs_sum[i] = abs_sum[i] + (*bk * p[k][i]).abs();
         }
-        rec.material = self.material.clone();
-        rec.t = t;
-        //三角法向量 = 2条边求叉积
-        let outward_normal = Vec3::cross(e1,e2).unit_vector();
-        rec.set_face_normal(ray,outward_normal);
-        rec.p = Some(ray.at(rec.t));
-        true
+    }
+    let g7 = gamma::<R>(7);
+    Some(TriangleHit{
+        t: t.to_f64(),
+        u: b[1].to_f64(),
+        v: b[2].to_f64(),
+        p: from_real3(hit_p),
+        p_error: from_real3(abs_sum.map(|a| a * g7)),
+    })
+}
 
+//按重心坐标填写击中记录 有顶点法线就插值出着色法线 有顶点 uv 就插值 uv 并反解切线
+pub(crate) fn set_triangle_hit(rec: &mut HitRecorder, ray: Ray, hit:TriangleHit, p:[Point3;3],
+                               normals:Option<[Vec3;3]>, uvs:Option<[(f64,f64);3]>) {
+    let TriangleHit{ t, u, v, p: hit_p, p_error } = hit;
+    let e1 = p[1] - p[0];
+    let e2 = p[2] - p[0];
+    rec.t = t;
+    //三角法向量 = 2条边求叉积
+    let outward_normal = Vec3::cross(e1,e2).unit_vector();
+    rec.set_face_normal(ray,outward_normal);
+    let w = 1.0 - u - v;
+    //顶点法线插值 并翻到和几何法线同一侧
+    if let Some([n1, n2, n3]) = normals {
+        let mut shading = n1 * w + n2 * u + n3 * v;
+        if !shading.near_zero() {
+            shading = shading.unit_vector();
+            if Vec3::dot(shading, rec.normal.unwrap()) < 0.0 {
+                shading = -shading;
+            }
+            rec.normal = Some(shading);
+        }
+    }
+    match uvs {
+        Some([uv1, uv2, uv3]) => {
+            rec.u = uv1.0 * w + uv2.0 * u + uv3.0 * v;
+            rec.v = uv1.1 * w + uv2.1 * u + uv3.1 * v;
+            //由 uv 的差值反解 dp/du dp/dv
+            let (du1, dv1) = (uv2.0 - uv1.0, uv2.1 - uv1.1);
+            let (du2, dv2) = (uv3.0 - uv1.0, uv3.1 - uv1.1);
+            let det = du1 * dv2 - dv1 * du2;
+            if f64_near_zero(det) {
+                rec.set_tangents(e1, e2);
+            } else {
+                rec.set_tangents((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det);
+            }
+        }
+        None => {
+            //用重心坐标作为 uv 对应的 dp/du dp/dv 就是两条边
+            rec.u = u;
+            rec.v = v;
+            rec.set_tangents(e1, e2);
+        }
+    }
+    rec.p = Some(hit_p);
+    rec.p_error = Some(p_error);
+}
+
+impl Hittable for Triangle{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let p = [self.p1, self.p2, self.p3];
+        match intersect_triangle::<f64>(p.map(to_real3), ray, t_min, t_max) {
+            None => { false }
+            Some(hit) => {
+                rec.material = self.material.clone();
+                set_triangle_hit(rec, ray, hit, p, None, None);
+                true
+            }
+        }
     }
     //计算三角面的 包围盒 求出 最小的 三个点 和最大三个点 构成的长方体
     fn bounding_box(&self) -> Option<AABB> {
@@ -197,6 +452,10 @@ impl Hittable for Triangle{
     fn random_sample(&self) -> Vec3 {
         todo!()
     }
+
+    fn triangle(&self) -> Option<[Point3;3]> {
+        Some([self.p1, self.p2, self.p3])
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -235,6 +494,26 @@ impl AABB{
         }
         return true;
     }
+
+    pub(crate) fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    //射线在包围盒里的区间 [t0, t1] 不相交返回 None
+    pub(crate) fn interval(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let inv_d = point3!(1.0 / ray.direction().x,1.0 / ray.direction().y,1.0 / ray.direction().z);
+        let t_in = (self.minimum - ray.origin()) * inv_d;
+        let t_out = (self.maximum - ray.origin()) * inv_d;
+        let min_t = Vec3::min(t_in,t_out);
+        let max_t = Vec3::max(t_in,t_out);
+        let t0 = f64::max(f64::max(min_t.x,f64::max(min_t.y,min_t.z)),t_min);
+        let t1 = f64::min(f64::min(max_t.x,f64::min(max_t.y,max_t.z)),t_max);
+        if t0 > t1{
+            return None
+        }
+        Some((t0, t1))
+    }
 }
 
 pub(crate) struct XyRect{
@@ -280,6 +559,7 @@ impl Hittable for XyRect{
         rec.t = t;
         let outward_normal = Vec3::form(0.0, 0.0, 1.0);
         rec.set_face_normal(ray, outward_normal);
+        rec.set_tangents(Vec3::form(self.x1 - self.x0, 0.0, 0.0), Vec3::form(0.0, self.y1 - self.y0, 0.0));
         rec.material = Some(self.mp.clone().unwrap());
         rec.p = Some(ray.at(t));
         return true;
@@ -353,6 +633,7 @@ impl Hittable for XzRect{
         rec.t = t;
         let outward_normal = Vec3::form(0.0, 1.0, 0.0);
         rec.set_face_normal(ray, outward_normal);
+        rec.set_tangents(Vec3::form(self.x1 - self.x0, 0.0, 0.0), Vec3::form(0.0, 0.0, self.z1 - self.z0));
         rec.material = Some(self.mp.clone().unwrap());
         rec.p = Some(ray.at(t));
         return true;
@@ -372,7 +653,7 @@ impl Hittable for XzRect{
     }
 
     fn pdf_value(&self,rec:&mut HitRecorder, p: Point3, to_light: Vec3) -> f64 {
-        if !self.hit(Ray::form(p,to_light.unit_vector()),0.0001,f64::MAX,rec){
+        if !self.hit(Ray::form(p,to_light.unit_vector()),0.0,f64::MAX,rec){
             return 0.0;
         }
         let light_cos_theta = to_light.unit_vector().y;
@@ -385,9 +666,10 @@ impl Hittable for XzRect{
     }
 
     fn random_sample(&self) -> Vec3 {
-        let x1 = rand_range_f64(213.0,343.0);
-        let x2 = rand_range_f64(227.0,332.0);
-        point3!(x1,554.0,x2)
+        let (sx, sz) = sample_2d();
+        let x = self.x0 + (self.x1 - self.x0) * sx;
+        let z = self.z0 + (self.z1 - self.z0) * sz;
+        point3!(x,self.k,z)
     }
 }
 
@@ -437,6 +719,7 @@ impl Hittable for YzRect{
         rec.t = t;
         let outward_normal = Vec3::form(1.0, 0.0, 0.0);
         rec.set_face_normal(ray, outward_normal);
+        rec.set_tangents(Vec3::form(0.0, self.y1 - self.y0, 0.0), Vec3::form(0.0, 0.0, self.z1 - self.z0));
         rec.material = Some(self.mp.clone().unwrap());
         rec.p = Some(ray.at(t));
         return true;
@@ -503,12 +786,18 @@ impl Hittable for MBox{
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
         let mut can_hit = false;
         let mut max_t = t_max;
-        for objs in self.sides.iter(){
+        let mut hit_side = 0;
+        for (i, objs) in self.sides.iter().enumerate(){
             if objs.clone().hit(ray,t_min,max_t,rec){
                 max_t = rec.t;
                 can_hit = true;
+                hit_side = i;
             }
         }
+        //每对面的第二个在坐标小的一侧 矩形的法线朝坐标轴正方向 对盒子来说是朝里的 进出要反过来
+        if can_hit && hit_side % 2 == 1 {
+            rec.front_face = !rec.front_face;
+        }
         can_hit
     }
 
@@ -601,17 +890,32 @@ impl Hittable for YRotate{
         let direction = ray.direction();
         let new_origin = Vec3::rotate_y(origin,-self.sin_theta,self.cos_theta);
         let new_dir = Vec3::rotate_y(direction,-self.sin_theta,self.cos_theta);
-        let rotated_ray = Ray::form (new_origin,new_dir);
+        let rotated_ray = Ray::form_time(new_origin,new_dir,ray.time());
         if !self.obj_ptr.clone().unwrap().hit(rotated_ray, t_min, t_max, rec) {
             return false;
         }
         let p = rec.p.unwrap();
         let normal = rec.normal.unwrap();
+        let geometric = rec.geometric_normal.unwrap();
 
         let rotated_p = Vec3::rotate_y(p,self.sin_theta,self.cos_theta);
         let rotated_normal = Vec3::rotate_y(normal,self.sin_theta,self.cos_theta);
         rec.p = Some(rotated_p);
+        //旋转会把 x z 的误差混在一起 再加上这次旋转的舍入
+        let p_error = rec.p_error.map(|e| {
+            let (s, c) = (self.sin_theta.abs(), self.cos_theta.abs());
+            let g = gamma::<f64>(3);
+            Vec3::form((c * e.x + s * e.z) * (1.0 + g) + (c * p.x.abs() + s * p.z.abs()) * g,
+                       e.y,
+                       (s * e.x + c * e.z) * (1.0 + g) + (s * p.x.abs() + c * p.z.abs()) * g)
+        });
         rec.set_face_normal(rotated_ray, rotated_normal);
+        rec.p_error = p_error;
+        rec.geometric_normal = Some(Vec3::rotate_y(geometric,self.sin_theta,self.cos_theta));
+        if let (Some(dpdu), Some(dpdv)) = (rec.tangent, rec.bitangent) {
+            rec.set_tangents(Vec3::rotate_y(dpdu,self.sin_theta,self.cos_theta),
+                             Vec3::rotate_y(dpdv,self.sin_theta,self.cos_theta));
+        }
         true
     }
 
@@ -661,14 +965,18 @@ impl Debug for Translate {
 impl Hittable for Translate{
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
         let new_origin = ray.origin() - self.offset;
-        let moved_ray = Ray::form(new_origin, ray.direction());
+        let moved_ray = Ray::form_time(new_origin, ray.direction(), ray.time());
         if !self.obj_ptr.clone().unwrap().hit(moved_ray, t_min, t_max, rec){
             return false
         }
         let mut p = rec.p.unwrap();
         p += self.offset;
         rec.p = Some(p);
+        let p_error = rec.p_error.map(|e| e + p.abs() * gamma::<f64>(1));
+        let geometric = rec.geometric_normal;
         rec.set_face_normal(moved_ray, rec.normal.unwrap());
+        rec.geometric_normal = geometric;
+        rec.p_error = p_error;
         true
     }
 
@@ -691,4 +999,782 @@ impl Hittable for Translate{
     fn random_sample(&self) -> Vec3 {
         todo!()
     }
-}
\ No newline at end of file
+}
+
+//把面积上均匀采样的 pdf 换算成立体角 pdf: 距离² / (面积 * cos)
+fn solid_angle_pdf(obj:&dyn Hittable, area:f64, rec:&mut HitRecorder, p:Point3, to_light:Vec3) -> f64{
+    let dir = to_light.unit_vector();
+    if !obj.hit(Ray::form(p, dir), 0.0, f64::MAX, rec) {
+        return 0.0;
+    }
+    let cos_theta = Vec3::dot(rec.normal.unwrap(), dir).abs();
+    if f64_near_zero(cos_theta) {
+        return 0.0;
+    }
+    rec.t * rec.t / (area * cos_theta)
+}
+
+//以 origin 为原点 axis 为 z 轴的局部坐标系 圆柱 圆锥 圆环在局部空间里求交
+#[derive(Copy, Clone)]
+struct LocalFrame{
+    origin:Point3,
+    t:Vec3,
+    b:Vec3,
+    n:Vec3,
+}
+
+impl LocalFrame{
+    fn form(origin:Point3, axis:Vec3) -> Self{
+        let n = axis.unit_vector();
+        let (t, b) = Vec3::orthonormal_basis(n);
+        Self{ origin, t, b, n }
+    }
+    fn to_local(self, v:Vec3) -> Vec3{
+        Vec3::form(Vec3::dot(v, self.t), Vec3::dot(v, self.b), Vec3::dot(v, self.n))
+    }
+    fn to_world(self, v:Vec3) -> Vec3{
+        self.t * v.x + self.b * v.y + self.n * v.z
+    }
+    fn local_ray(&self, ray:Ray) -> Ray{
+        Ray::form_time(self.to_local(ray.origin() - self.origin), self.to_local(ray.direction()), ray.time())
+    }
+    //局部空间里的包围盒 8 个角变到世界空间再求包围盒
+    fn world_box(&self, min:Vec3, max:Vec3) -> AABB{
+        let mut lo = point3!(f64::MAX,f64::MAX,f64::MAX);
+        let mut hi = point3!(f64::MIN,f64::MIN,f64::MIN);
+        for i in 0..8{
+            let corner = point3!(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z });
+            let p = self.origin + self.to_world(corner);
+            lo = Vec3::min(lo, p);
+            hi = Vec3::max(hi, p);
+        }
+        //平面物体的包围盒在某个轴上厚度为 0 稍微撑开一点
+        AABB::form(lo - 0.0001, hi + 0.0001)
+    }
+}
+
+
+//平行四边形 q 为一个角 u v 为两条边
+pub(crate) struct Quad{
+    q:Point3,
+    u:Vec3,
+    v:Vec3,
+    normal:Vec3,
+    w:Vec3,
+    d:f64,
+    area:f64,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Quad{
+    pub(crate) fn form(q:Point3, u:Vec3, v:Vec3, ma:Arc<dyn Materials>) -> Self{
+        let n = Vec3::cross(u, v);
+        let normal = n.unit_vector();
+        Self{
+            q,
+            u,
+            v,
+            normal,
+            w: n / Vec3::dot(n, n),
+            d: Vec3::dot(normal, q),
+            area: n.length(),
+            mp: Some(ma),
+        }
+    }
+}
+
+impl Debug for Quad {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Quad({:?}, {:?}, {:?})",self.q,self.u,self.v)
+    }
+}
+
+impl Hittable for Quad{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let denom = Vec3::dot(self.normal, ray.direction());
+        if f64_near_zero(denom) {
+            return false;
+        }
+        let t = (self.d - Vec3::dot(self.normal, ray.origin())) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+        let p = ray.at(t);
+        let planar = p - self.q;
+        //p = q + alpha * u + beta * v 解出 alpha beta
+        let alpha = Vec3::dot(self.w, Vec3::cross(planar, self.v));
+        let beta = Vec3::dot(self.w, Vec3::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+        rec.t = t;
+        rec.p = Some(p);
+        rec.u = alpha;
+        rec.v = beta;
+        rec.material = self.mp.clone();
+        rec.set_face_normal(ray, self.normal);
+        rec.set_tangents(self.u, self.v);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let mut min = corners[0];
+        let mut max = corners[0];
+        for c in corners.iter(){
+            min = Vec3::min(min, *c);
+            max = Vec3::max(max, *c);
+        }
+        Some(AABB::form(min - 0.0001, max + 0.0001))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.q + (self.u + self.v) / 2.0)
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        solid_angle_pdf(self, self.area, rec, p, dir)
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        let (a, b) = sample_2d();
+        self.q + self.u * a + self.v * b
+    }
+}
+
+
+//圆盘 u 为极角 v 为到圆心的距离 / 半径
+pub(crate) struct Disk{
+    frame:LocalFrame,
+    radius:f64,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Disk{
+    pub(crate) fn form(center:Point3, normal:Vec3, radius:f64, ma:Arc<dyn Materials>) -> Self{
+        Self{
+            frame: LocalFrame::form(center, normal),
+            radius,
+            mp: Some(ma),
+        }
+    }
+}
+
+impl Debug for Disk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Disk({:?}, {})",self.frame.origin,self.radius)
+    }
+}
+
+//局部空间 z = height 处半径为 radius 的圆盘求交 返回 (t, 局部击中点)
+fn hit_local_disk(local:Ray, height:f64, radius:f64, t_min:f64, t_max:f64) -> Option<(f64, Vec3)>{
+    let dz = local.direction().z;
+    if f64_near_zero(dz) {
+        return None;
+    }
+    let t = (height - local.origin().z) / dz;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    let p = local.at(t);
+    if p.x * p.x + p.y * p.y > radius * radius {
+        return None;
+    }
+    Some((t, p))
+}
+
+//圆盘上的 uv 和切线 切线先在局部空间算好再变回世界空间
+fn set_disk_hit(rec:&mut HitRecorder, ray:Ray, frame:&LocalFrame, t:f64, p:Vec3, radius:f64, normal:Vec3){
+    let dist = (p.x * p.x + p.y * p.y).sqrt();
+    let phi = p.y.atan2(p.x);
+    rec.t = t;
+    rec.p = Some(ray.at(t));
+    rec.u = (phi + PI) / (2.0 * PI);
+    rec.v = dist / radius;
+    rec.set_face_normal(ray, frame.to_world(normal));
+    if f64_near_zero(dist) {
+        rec.set_tangents(frame.t, frame.b);
+    } else {
+        let radial = Vec3::form(p.x / dist, p.y / dist, 0.0);
+        let dpdu = Vec3::form(-p.y, p.x, 0.0) * (2.0 * PI);
+        rec.set_tangents(frame.to_world(dpdu), frame.to_world(radial * radius));
+    }
+}
+
+//单位圆盘上均匀采样
+fn random_in_disk(radius:f64) -> (f64, f64){
+    let (a, b) = sample_2d();
+    let r = radius * a.sqrt();
+    let phi = 2.0 * PI * b;
+    (r * phi.cos(), r * phi.sin())
+}
+
+impl Hittable for Disk{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let local = self.frame.local_ray(ray);
+        match hit_local_disk(local, 0.0, self.radius, t_min, t_max) {
+            None => { false }
+            Some((t, p)) => {
+                rec.material = self.mp.clone();
+                set_disk_hit(rec, ray, &self.frame, t, p, self.radius, Vec3::form(0.0, 0.0, 1.0));
+                true
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = self.radius;
+        Some(self.frame.world_box(point3!(-r, -r, 0), point3!(r, r, 0)))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.frame.origin)
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        solid_angle_pdf(self, PI * self.radius * self.radius, rec, p, dir)
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        let (x, y) = random_in_disk(self.radius);
+        self.frame.origin + self.frame.to_world(point3!(x, y, 0))
+    }
+}
+
+
+//有上下底的圆柱 base 为底面圆心 沿 axis 方向高 height
+pub(crate) struct Cylinder{
+    frame:LocalFrame,
+    radius:f64,
+    height:f64,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Cylinder{
+    pub(crate) fn form(base:Point3, axis:Vec3, radius:f64, height:f64, ma:Arc<dyn Materials>) -> Self{
+        Self{
+            frame: LocalFrame::form(base, axis),
+            radius,
+            height,
+            mp: Some(ma),
+        }
+    }
+}
+
+impl Debug for Cylinder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Cylinder({:?}, {}, {})",self.frame.origin,self.radius,self.height)
+    }
+}
+
+//侧面 u 为极角 v 由调用方按高度比例设置
+fn set_side_hit(rec:&mut HitRecorder, ray:Ray, frame:&LocalFrame, t:f64, p:Vec3, local_normal:Vec3, dpdv:Vec3){
+    rec.t = t;
+    rec.p = Some(ray.at(t));
+    rec.u = (p.y.atan2(p.x) + PI) / (2.0 * PI);
+    rec.set_face_normal(ray, frame.to_world(local_normal.unit_vector()));
+    rec.set_tangents(frame.to_world(Vec3::form(-p.y, p.x, 0.0) * (2.0 * PI)), frame.to_world(dpdv));
+}
+
+//一元二次方程在 (t_min, t_max) 内的两个根 从小到大
+fn solve_quadratic(a:f64, half_b:f64, c:f64) -> Option<(f64, f64)>{
+    if f64_near_zero(a) {
+        return None;
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let r0 = (-half_b - sqrtd) / a;
+    let r1 = (-half_b + sqrtd) / a;
+    Some((f64::min(r0, r1), f64::max(r0, r1)))
+}
+
+impl Hittable for Cylinder{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let local = self.frame.local_ray(ray);
+        let o = local.origin();
+        let d = local.direction();
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        if let Some((r0, r1)) = solve_quadratic(d.x * d.x + d.y * d.y, o.x * d.x + o.y * d.y,
+                                                o.x * o.x + o.y * o.y - self.radius * self.radius) {
+            for t in [r0, r1]{
+                if t < t_min || t > closest {
+                    continue;
+                }
+                let p = local.at(t);
+                if p.z < 0.0 || p.z > self.height {
+                    continue;
+                }
+                set_side_hit(rec, ray, &self.frame, t, p, Vec3::form(p.x, p.y, 0.0), Vec3::form(0.0, 0.0, self.height));
+                rec.v = p.z / self.height;
+                closest = t;
+                hit_anything = true;
+                break;
+            }
+        }
+        for (z, normal) in [(0.0, -1.0), (self.height, 1.0)]{
+            if let Some((t, p)) = hit_local_disk(local, z, self.radius, t_min, closest) {
+                set_disk_hit(rec, ray, &self.frame, t, p, self.radius, Vec3::form(0.0, 0.0, normal));
+                closest = t;
+                hit_anything = true;
+            }
+        }
+        if hit_anything {
+            rec.material = self.mp.clone();
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = self.radius;
+        Some(self.frame.world_box(point3!(-r, -r, 0), point3!(r, r, self.height)))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.frame.origin + self.frame.n * (self.height / 2.0))
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        let area = 2.0 * PI * self.radius * (self.radius + self.height);
+        solid_angle_pdf(self, area, rec, p, dir)
+    }
+
+    //按面积比例选择侧面或者底面
+    fn random_sample(&self) -> Vec3 {
+        let side = self.height / (self.radius + self.height);
+        let r = sample_1d();
+        let local = if r < side {
+            let (a, b) = sample_2d();
+            let phi = 2.0 * PI * a;
+            point3!(self.radius * phi.cos(), self.radius * phi.sin(), self.height * b)
+        } else {
+            let (x, y) = random_in_disk(self.radius);
+            point3!(x, y, if r < side + (1.0 - side) / 2.0 { 0.0 } else { self.height })
+        };
+        self.frame.origin + self.frame.to_world(local)
+    }
+}
+
+
+//圆锥 base 为底面圆心 顶点在 base + axis * height 底面封口
+pub(crate) struct Cone{
+    frame:LocalFrame,
+    radius:f64,
+    height:f64,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Cone{
+    pub(crate) fn form(base:Point3, axis:Vec3, radius:f64, height:f64, ma:Arc<dyn Materials>) -> Self{
+        Self{
+            frame: LocalFrame::form(base, axis),
+            radius,
+            height,
+            mp: Some(ma),
+        }
+    }
+
+    fn slant(&self) -> f64{
+        (self.radius * self.radius + self.height * self.height).sqrt()
+    }
+}
+
+impl Debug for Cone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Cone({:?}, {}, {})",self.frame.origin,self.radius,self.height)
+    }
+}
+
+impl Hittable for Cone{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let local = self.frame.local_ray(ray);
+        let o = local.origin();
+        let d = local.direction();
+        //侧面方程 x² + y² = k²(h - z)²
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let hz = self.height - o.z;
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        if let Some((r0, r1)) = solve_quadratic(d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+                                                o.x * d.x + o.y * d.y + k2 * hz * d.z,
+                                                o.x * o.x + o.y * o.y - k2 * hz * hz) {
+            for t in [r0, r1]{
+                if t < t_min || t > closest {
+                    continue;
+                }
+                let p = local.at(t);
+                if p.z < 0.0 || p.z > self.height {
+                    continue;
+                }
+                let normal = Vec3::form(p.x, p.y, k2 * (self.height - p.z));
+                //dp/dv 从底面沿母线指向顶点 即 (-r cosφ, -r sinφ, h)
+                let ring = (p.x * p.x + p.y * p.y).sqrt();
+                let dpdv = if f64_near_zero(ring) {
+                    Vec3::form(0.0, 0.0, self.height)
+                } else {
+                    Vec3::form(-p.x / ring * self.radius, -p.y / ring * self.radius, self.height)
+                };
+                set_side_hit(rec, ray, &self.frame, t, p, normal, dpdv);
+                rec.v = p.z / self.height;
+                closest = t;
+                hit_anything = true;
+                break;
+            }
+        }
+        if let Some((t, p)) = hit_local_disk(local, 0.0, self.radius, t_min, closest) {
+            set_disk_hit(rec, ray, &self.frame, t, p, self.radius, Vec3::form(0.0, 0.0, -1.0));
+            hit_anything = true;
+        }
+        if hit_anything {
+            rec.material = self.mp.clone();
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = self.radius;
+        Some(self.frame.world_box(point3!(-r, -r, 0), point3!(r, r, self.height)))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.frame.origin + self.frame.n * (self.height / 4.0))
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        let area = PI * self.radius * (self.radius + self.slant());
+        solid_angle_pdf(self, area, rec, p, dir)
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        let side = self.slant() / (self.radius + self.slant());
+        let local = if sample_1d() < side {
+            //侧面上到顶点的距离比例 s 的概率密度正比于 s
+            let (a, b) = sample_2d();
+            let s = a.sqrt();
+            let phi = 2.0 * PI * b;
+            point3!(self.radius * s * phi.cos(), self.radius * s * phi.sin(), self.height * (1.0 - s))
+        } else {
+            let (x, y) = random_in_disk(self.radius);
+            point3!(x, y, 0)
+        };
+        self.frame.origin + self.frame.to_world(local)
+    }
+}
+
+
+//圆环 major 为圆环中心到管中心的半径 minor 为管的半径 axis 为对称轴
+pub(crate) struct Torus{
+    frame:LocalFrame,
+    major:f64,
+    minor:f64,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Torus{
+    pub(crate) fn form(center:Point3, axis:Vec3, major:f64, minor:f64, ma:Arc<dyn Materials>) -> Self{
+        Self{
+            frame: LocalFrame::form(center, axis),
+            major,
+            minor,
+            mp: Some(ma),
+        }
+    }
+}
+
+impl Debug for Torus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Torus({:?}, {}, {})",self.frame.origin,self.major,self.minor)
+    }
+}
+
+//实系数一元三次方程 a t³ + b t² + c t + d = 0 的所有实根
+fn solve_cubic(a:f64, b:f64, c:f64, d:f64) -> Vec<f64>{
+    if f64_near_zero(a) {
+        return match solve_quadratic(b, c / 2.0, d) {
+            None => { vec![] }
+            Some((r0, r1)) => { vec![r0, r1] }
+        };
+    }
+    //化成 x³ + px + q = 0 的形式 t = x - b / 3a
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = b / 3.0;
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let disc = q * q / 4.0 + p * p * p / 27.0;
+    if disc > 0.0 {
+        let s = disc.sqrt();
+        return vec![(-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt() - shift];
+    }
+    if f64_near_zero(p) {
+        return vec![-shift];
+    }
+    //三个实根 用三角函数解法
+    let r = (-p / 3.0).sqrt();
+    let phi = (clamp_unit(-q / (2.0 * r * r * r))).acos();
+    (0..3).map(|k| 2.0 * r * ((phi + 2.0 * PI * k as f64) / 3.0).cos() - shift).collect()
+}
+
+fn clamp_unit(x:f64) -> f64{
+    x.clamp(-1.0, 1.0)
+}
+
+impl Torus{
+    //局部空间的四次方程 f(t) = (|p|² + R² - r²)² - 4R²(x² + y²) 的系数 从高次到低次
+    fn quartic(&self, o:Vec3, d:Vec3) -> [f64;5]{
+        let r2 = self.major * self.major;
+        let dd = Vec3::dot(d, d);
+        let od = Vec3::dot(o, d);
+        let oo = Vec3::dot(o, o);
+        let k = oo + r2 - self.minor * self.minor;
+        [
+            dd * dd,
+            4.0 * dd * od,
+            2.0 * dd * k + 4.0 * od * od - 4.0 * r2 * (d.x * d.x + d.y * d.y),
+            4.0 * od * k - 8.0 * r2 * (o.x * d.x + o.y * d.y),
+            k * k - 4.0 * r2 * (o.x * o.x + o.y * o.y),
+        ]
+    }
+
+    //用导数(三次方程)的根把区间切成单调段 每段上有变号就二分 得到最小的根
+    fn first_root(&self, c:[f64;5], t0:f64, t1:f64) -> Option<f64>{
+        let f = |t:f64| (((c[0] * t + c[1]) * t + c[2]) * t + c[3]) * t + c[4];
+        let mut points = vec![t0];
+        let mut critical = solve_cubic(4.0 * c[0], 3.0 * c[1], 2.0 * c[2], c[3]);
+        critical.sort_by(|a, b| cmp_f64(*a, *b));
+        for x in critical{
+            if x > t0 && x < t1 {
+                points.push(x);
+            }
+        }
+        points.push(t1);
+        for pair in points.windows(2){
+            let (mut lo, mut hi) = (pair[0], pair[1]);
+            let (flo, fhi) = (f(lo), f(hi));
+            if flo == 0.0 {
+                return Some(lo);
+            }
+            if flo * fhi > 0.0 {
+                continue;
+            }
+            for _i in 0..64{
+                let mid = (lo + hi) / 2.0;
+                if f(mid) * flo > 0.0 {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            return Some((lo + hi) / 2.0);
+        }
+        None
+    }
+}
+
+impl Hittable for Torus{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let local = self.frame.local_ray(ray);
+        let o = local.origin();
+        let d = local.direction();
+        //先和包围球求交 把求根区间限制在包围球内
+        let bound = self.major + self.minor;
+        let (s0, s1) = match solve_quadratic(Vec3::dot(d, d), Vec3::dot(o, d), Vec3::dot(o, o) - bound * bound) {
+            None => { return false }
+            Some(r) => { r }
+        };
+        let t0 = f64::max(s0, t_min);
+        let t1 = f64::min(s1, t_max);
+        if t0 >= t1 {
+            return false;
+        }
+        let t = match self.first_root(self.quartic(o, d), t0, t1) {
+            None => { return false }
+            Some(t) => { t }
+        };
+        let p = local.at(t);
+        let ring = (p.x * p.x + p.y * p.y).sqrt();
+        //管中心 指向击中点的方向就是法线
+        let tube_center = if f64_near_zero(ring) { Vec3::new() } else { Vec3::form(p.x, p.y, 0.0) * (self.major / ring) };
+        let normal = (p - tube_center).unit_vector();
+        let phi = p.y.atan2(p.x);
+        let theta = p.z.atan2(ring - self.major);
+        rec.t = t;
+        rec.p = Some(ray.at(t));
+        rec.u = (phi + PI) / (2.0 * PI);
+        rec.v = (theta + PI) / (2.0 * PI);
+        rec.material = self.mp.clone();
+        rec.set_face_normal(ray, self.frame.to_world(normal));
+        let dpdu = Vec3::form(-p.y, p.x, 0.0) * (2.0 * PI);
+        let dpdv = Vec3::form(-theta.sin() * phi.cos(), -theta.sin() * phi.sin(), theta.cos()) * (2.0 * PI * self.minor);
+        rec.set_tangents(self.frame.to_world(dpdu), self.frame.to_world(dpdv));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let r = self.major + self.minor;
+        Some(self.frame.world_box(point3!(-r, -r, -self.minor), point3!(r, r, self.minor)))
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.frame.origin)
+    }
+
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        let area = 4.0 * PI * PI * self.major * self.minor;
+        solid_angle_pdf(self, area, rec, p, dir)
+    }
+
+    //外圈面积更大 按 (R + r cosθ) / (R + r) 的概率接受
+    fn random_sample(&self) -> Vec3 {
+        loop{
+            let (a, b) = sample_2d();
+            let theta = 2.0 * PI * a;
+            if b * (self.major + self.minor) > self.major + self.minor * theta.cos() {
+                continue;
+            }
+            let phi = 2.0 * PI * sample_1d();
+            let ring = self.major + self.minor * theta.cos();
+            let local = point3!(ring * phi.cos(), ring * phi.sin(), self.minor * theta.sin());
+            return self.frame.origin + self.frame.to_world(local);
+        }
+    }
+}
+
+
+//无限大的平面 没有包围盒 不能放进 BVH 也不能作为光源采样
+pub(crate) struct Plane{
+    frame:LocalFrame,
+    mp:Option<Arc<dyn Materials>>,
+}
+
+impl Plane{
+    pub(crate) fn form(point:Point3, normal:Vec3, ma:Arc<dyn Materials>) -> Self{
+        Self{
+            frame: LocalFrame::form(point, normal),
+            mp: Some(ma),
+        }
+    }
+}
+
+impl Debug for Plane {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Plane({:?}, {:?})",self.frame.origin,self.frame.n)
+    }
+}
+
+impl Hittable for Plane{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let denom = Vec3::dot(self.frame.n, ray.direction());
+        if f64_near_zero(denom) {
+            return false;
+        }
+        let t = Vec3::dot(self.frame.origin - ray.origin(), self.frame.n) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+        let p = ray.at(t);
+        let local = self.frame.to_local(p - self.frame.origin);
+        //uv 直接用平面上的坐标 配合 UvCheckerTexture 这类纹理使用
+        rec.t = t;
+        rec.p = Some(p);
+        rec.u = local.x;
+        rec.v = local.y;
+        rec.material = self.mp.clone();
+        rec.set_face_normal(ray, self.frame.n);
+        rec.set_tangents(self.frame.t, self.frame.b);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        None
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call(self.frame.origin)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        0.0
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        panic!("无限大的平面不能采样!")
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::common::rand_f64;
+
+    //平行四边形沿对角线 a-b 分成两个三角形 射向对角线上的点 每条射线必须正好打中其中一个 不能漏过去也不能两个都中
+    fn shared_edge_hits<R:Real>(){
+        let a = Vec3::form(0.3, -1.7, 2.1);
+        let b = Vec3::form(1.9, 0.6, -0.4);
+        let c = Vec3::form(-1.2, 1.1, 0.7);
+        let d = a + b - c;
+        let normal = Vec3::cross(b - a, c - a).unit_vector();
+        let first = [a, b, c].map(to_real3::<R>);
+        let second = [b, a, d].map(to_real3::<R>);
+        for i in 0..20000{
+            let s = rand_f64();
+            let target = a + (b - a) * s;
+            //从两面斜着射过来 和平面的夹角至少 26 度 太平的射线在 f32 下投影出来的三角形已经退化了
+            let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+            let r = Vec3::random_in_unit_sphere();
+            let origin = target + normal * (side * (0.5 + rand_f64())) + (r - normal * Vec3::dot(r, normal));
+            let ray = Ray::form(origin, target - origin);
+            let hits = [first, second].iter().filter(|t| intersect_triangle(**t, ray, 0.0, f64::MAX).is_some()).count();
+            assert_eq!(hits, 1, "s = {} origin = {:?}", s, origin);
+        }
+    }
+
+    #[test]
+    fn watertight_shared_edge_f64(){
+        shared_edge_hits::<f64>();
+    }
+
+    #[test]
+    fn watertight_shared_edge_f32(){
+        shared_edge_hits::<f32>();
+    }
+
+    //同一个击中记录先打中带误差上界的球 再打中矩形 不能沿用球的误差
+    #[test]
+    fn hit_does_not_keep_stale_p_error(){
+        let material = Arc::new(Lambertian::form_color(0.5, 0.5, 0.5));
+        let sphere = Sphere::form(Vec3::form(0.0, 0.0, -5.0), 1.0, material.clone());
+        let rect = XyRect::form(-1.0, 1.0, -1.0, 1.0, -2.0, material);
+        let ray = Ray::form(Vec3::new(), Vec3::form(0.0, 0.0, -1.0));
+        let mut rec = HitRecorder::new();
+        assert!(sphere.hit(ray, 0.0, f64::MAX, &mut rec));
+        assert!(rec.p_error.is_some());
+        assert!(rect.hit(ray, 0.0, f64::MAX, &mut rec));
+        assert!(rec.p_error.is_none());
+        assert!(Translate::form(Arc::new(sphere), Vec3::form(0.0, 0.0, 1.0)).hit(ray, 0.0, f64::MAX, &mut rec));
+        assert!(rec.p_error.is_some());
+    }
+
+    //穿过盒子的射线 先进入再离开 不管打中的是哪一侧的面
+    #[test]
+    fn box_faces_report_entering_and_leaving(){
+        let cube = MBox::form(Vec3::form(-1.0, -1.0, -1.0), Vec3::form(1.0, 1.0, 1.0), Arc::new(Lambertian::form_color(0.5, 0.5, 0.5)));
+        for dir in [Vec3::form(1.0, 0.1, 0.2), Vec3::form(-1.0, 0.1, 0.2), Vec3::form(0.1, 1.0, 0.2),
+                    Vec3::form(0.1, -1.0, 0.2), Vec3::form(0.1, 0.2, 1.0), Vec3::form(0.1, 0.2, -1.0)]{
+            let ray = Ray::form(-dir * 5.0, dir);
+            let mut rec = HitRecorder::new();
+            assert!(cube.hit(ray, 0.0, f64::MAX, &mut rec));
+            assert!(rec.front_face);
+            let mut rec = HitRecorder::new();
+            assert!(cube.hit(Ray::form(Vec3::new(), dir), 0.0, f64::MAX, &mut rec));
+            assert!(!rec.front_face);
+        }
+    }
+}
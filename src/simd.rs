@@ -0,0 +1,255 @@
+//4 个 f64 一起算 编译时打开了 avx 就用 avx 指令 其他 x86_64 用两个 sse2 寄存器(x86_64 一定支持 sse2)
+//别的平台逐个计算 比较的结果是 4 位的掩码 第 i 位对应第 i 个数
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+mod imp{
+    use std::arch::x86_64::*;
+    use std::ops::{Add, Sub, Mul, Div};
+
+    #[derive(Debug, Copy, Clone)]
+    pub(crate) struct F64x4(__m256d);
+
+    impl F64x4{
+        pub(crate) fn splat(v:f64) -> Self{
+            unsafe { Self(_mm256_set1_pd(v)) }
+        }
+        pub(crate) fn form(v:[f64;4]) -> Self{
+            unsafe { Self(_mm256_loadu_pd(v.as_ptr())) }
+        }
+        pub(crate) fn to_array(self) -> [f64;4]{
+            let mut out = [0.0;4];
+            unsafe { _mm256_storeu_pd(out.as_mut_ptr(), self.0) }
+            out
+        }
+        pub(crate) fn min(self, o:Self) -> Self{
+            unsafe { Self(_mm256_min_pd(self.0, o.0)) }
+        }
+        pub(crate) fn max(self, o:Self) -> Self{
+            unsafe { Self(_mm256_max_pd(self.0, o.0)) }
+        }
+        pub(crate) fn le(self, o:Self) -> u8{
+            unsafe { _mm256_movemask_pd(_mm256_cmp_pd::<_CMP_LE_OQ>(self.0, o.0)) as u8 }
+        }
+        pub(crate) fn gt(self, o:Self) -> u8{
+            unsafe { _mm256_movemask_pd(_mm256_cmp_pd::<_CMP_GT_OQ>(self.0, o.0)) as u8 }
+        }
+    }
+
+    impl Add for F64x4{
+        type Output = F64x4;
+        fn add(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm256_add_pd(self.0, o.0)) }
+        }
+    }
+
+    impl Sub for F64x4{
+        type Output = F64x4;
+        fn sub(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm256_sub_pd(self.0, o.0)) }
+        }
+    }
+
+    impl Mul for F64x4{
+        type Output = F64x4;
+        fn mul(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm256_mul_pd(self.0, o.0)) }
+        }
+    }
+
+    impl Div for F64x4{
+        type Output = F64x4;
+        fn div(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm256_div_pd(self.0, o.0)) }
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(target_feature = "avx")))]
+mod imp{
+    use std::arch::x86_64::*;
+    use std::ops::{Add, Sub, Mul, Div};
+
+    //低两个数和高两个数各放一个寄存器
+    #[derive(Debug, Copy, Clone)]
+    pub(crate) struct F64x4(__m128d, __m128d);
+
+    impl F64x4{
+        pub(crate) fn splat(v:f64) -> Self{
+            unsafe { Self(_mm_set1_pd(v), _mm_set1_pd(v)) }
+        }
+        pub(crate) fn form(v:[f64;4]) -> Self{
+            unsafe { Self(_mm_loadu_pd(v.as_ptr()), _mm_loadu_pd(v.as_ptr().add(2))) }
+        }
+        pub(crate) fn to_array(self) -> [f64;4]{
+            let mut out = [0.0;4];
+            unsafe {
+                _mm_storeu_pd(out.as_mut_ptr(), self.0);
+                _mm_storeu_pd(out.as_mut_ptr().add(2), self.1);
+            }
+            out
+        }
+        //和 avx 一样 有 NaN 时取第二个数
+        pub(crate) fn min(self, o:Self) -> Self{
+            unsafe { Self(_mm_min_pd(self.0, o.0), _mm_min_pd(self.1, o.1)) }
+        }
+        pub(crate) fn max(self, o:Self) -> Self{
+            unsafe { Self(_mm_max_pd(self.0, o.0), _mm_max_pd(self.1, o.1)) }
+        }
+        pub(crate) fn le(self, o:Self) -> u8{
+            unsafe { (_mm_movemask_pd(_mm_cmple_pd(self.0, o.0)) | _mm_movemask_pd(_mm_cmple_pd(self.1, o.1)) << 2) as u8 }
+        }
+        pub(crate) fn gt(self, o:Self) -> u8{
+            unsafe { (_mm_movemask_pd(_mm_cmpgt_pd(self.0, o.0)) | _mm_movemask_pd(_mm_cmpgt_pd(self.1, o.1)) << 2) as u8 }
+        }
+    }
+
+    impl Add for F64x4{
+        type Output = F64x4;
+        fn add(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm_add_pd(self.0, o.0), _mm_add_pd(self.1, o.1)) }
+        }
+    }
+
+    impl Sub for F64x4{
+        type Output = F64x4;
+        fn sub(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm_sub_pd(self.0, o.0), _mm_sub_pd(self.1, o.1)) }
+        }
+    }
+
+    impl Mul for F64x4{
+        type Output = F64x4;
+        fn mul(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm_mul_pd(self.0, o.0), _mm_mul_pd(self.1, o.1)) }
+        }
+    }
+
+    impl Div for F64x4{
+        type Output = F64x4;
+        fn div(self, o: Self) -> Self::Output {
+            unsafe { Self(_mm_div_pd(self.0, o.0), _mm_div_pd(self.1, o.1)) }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod imp{
+    use std::ops::{Add, Sub, Mul, Div};
+
+    #[derive(Debug, Copy, Clone)]
+    pub(crate) struct F64x4([f64;4]);
+
+    impl F64x4{
+        fn map(self, o:Self, f:impl Fn(f64, f64) -> f64) -> Self{
+            Self([f(self.0[0], o.0[0]), f(self.0[1], o.0[1]), f(self.0[2], o.0[2]), f(self.0[3], o.0[3])])
+        }
+        fn mask(self, o:Self, f:impl Fn(f64, f64) -> bool) -> u8{
+            let mut m = 0;
+            for i in 0..4{
+                if f(self.0[i], o.0[i]) {
+                    m |= 1 << i;
+                }
+            }
+            m
+        }
+        pub(crate) fn splat(v:f64) -> Self{
+            Self([v;4])
+        }
+        pub(crate) fn form(v:[f64;4]) -> Self{
+            Self(v)
+        }
+        pub(crate) fn to_array(self) -> [f64;4]{
+            self.0
+        }
+        //和 avx 的 min max 一样 有 NaN 时取第二个数
+        pub(crate) fn min(self, o:Self) -> Self{
+            self.map(o, |a, b| if a < b { a } else { b })
+        }
+        pub(crate) fn max(self, o:Self) -> Self{
+            self.map(o, |a, b| if a > b { a } else { b })
+        }
+        pub(crate) fn le(self, o:Self) -> u8{
+            self.mask(o, |a, b| a <= b)
+        }
+        pub(crate) fn gt(self, o:Self) -> u8{
+            self.mask(o, |a, b| a > b)
+        }
+    }
+
+    impl Add for F64x4{
+        type Output = F64x4;
+        fn add(self, o: Self) -> Self::Output {
+            self.map(o, |a, b| a + b)
+        }
+    }
+
+    impl Sub for F64x4{
+        type Output = F64x4;
+        fn sub(self, o: Self) -> Self::Output {
+            self.map(o, |a, b| a - b)
+        }
+    }
+
+    impl Mul for F64x4{
+        type Output = F64x4;
+        fn mul(self, o: Self) -> Self::Output {
+            self.map(o, |a, b| a * b)
+        }
+    }
+
+    impl Div for F64x4{
+        type Output = F64x4;
+        fn div(self, o: Self) -> Self::Output {
+            self.map(o, |a, b| a / b)
+        }
+    }
+}
+
+pub(crate) use imp::F64x4;
+
+//按分量存的 4 个向量
+pub(crate) type Vec3x4 = [F64x4;3];
+
+pub(crate) fn dot4(a:Vec3x4, b:Vec3x4) -> F64x4{
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross4(a:Vec3x4, b:Vec3x4) -> Vec3x4{
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub(crate) fn sub4(a:Vec3x4, b:Vec3x4) -> Vec3x4{
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //和逐个计算的结果一致 包括 NaN 时 min max 取第二个数 比较为 false
+    #[test]
+    fn lanes_match_scalar(){
+        let a = [1.0, -2.5, f64::NAN, 4.0];
+        let b = [3.0, -2.5, 0.5, f64::NAN];
+        let (x, y) = (F64x4::form(a), F64x4::form(b));
+        for (i, v) in (x + y).to_array().iter().enumerate(){
+            assert_eq!(v.to_bits(), (a[i] + b[i]).to_bits());
+        }
+        assert_eq!((x * y).to_array()[1], 6.25);
+        assert_eq!((x / y).to_array()[0], 1.0 / 3.0);
+        assert!((x - y).to_array()[3].is_nan());
+        assert_eq!(x.min(y).to_array()[..3], [1.0, -2.5, 0.5]);
+        assert!(x.min(y).to_array()[3].is_nan());
+        assert_eq!(x.max(y).to_array()[..3], [3.0, -2.5, 0.5]);
+        assert_eq!(x.le(y), 0b0011);
+        assert_eq!(y.gt(x), 0b0001);
+        assert_eq!(F64x4::splat(2.0).to_array(), [2.0;4]);
+    }
+
+    #[test]
+    fn vector_products(){
+        let a = [F64x4::splat(1.0), F64x4::splat(0.0), F64x4::splat(0.0)];
+        let b = [F64x4::splat(0.0), F64x4::form([1.0, 2.0, 3.0, 4.0]), F64x4::splat(0.0)];
+        assert_eq!(dot4(a, b).to_array(), [0.0;4]);
+        assert_eq!(cross4(a, b)[2].to_array(), [1.0, 2.0, 3.0, 4.0]);
+    }
+}
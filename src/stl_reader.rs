@@ -1,33 +1,116 @@
-use std::fs::File;
-use std::io::Read;
-use crate::common::{parse_i32_little_endian, parse_f32_little_endian, degrees_to_radians};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use crate::common::{parse_i32_little_endian, parse_f32_little_endian};
 use crate::vec3::Vec3;
 use std::sync::Arc;
-use crate::shape::Triangle;
-use crate::hit::Hittable;
 use crate::material::Materials;
 use crate::{point3};
-use crate::bvh::BvhNode;
-use crate::hittable_list::HittableList;
+use crate::wide_bvh::WideBvh;
 use crate::Point3;
-trait Reader{
-    fn reader_next();
+use crate::mesh::{MeshTransform, TriangleMesh};
+use std::collections::HashMap;
+
+//二进制 stl: 80 字节文件头 + 4 字节三角形数量 + 每个三角形 50 字节
+const HEADER_LEN:usize = 80;
+const FACET_LEN:usize = 50;
+
+#[derive(Debug)]
+pub(crate) enum StlError{
+    Io(PathBuf, std::io::Error),
+    //文件比二进制头还短 又不是 ascii
+    TooShort{len:usize},
+    //二进制文件声明的三角形数量和文件大小对不上
+    SizeMismatch{declared:usize, expected_len:usize, actual_len:usize},
+    Parse{line:usize, msg:String},
+}
+
+impl Display for StlError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StlError::Io(path, e) => { write!(f,"读取 {} 失败: {}",path.display(),e) }
+            StlError::TooShort{len} => { write!(f,"文件只有 {} 字节 不是有效的 stl",len) }
+            StlError::SizeMismatch{declared, expected_len, actual_len} => {
+                write!(f,"声明了 {} 个三角形 应该有 {} 字节 实际 {} 字节",declared,expected_len,actual_len)
+            }
+            StlError::Parse{line, msg} => { write!(f,"第 {} 行解析失败: {}",line,msg) }
+        }
+    }
+}
+
+impl std::error::Error for StlError{}
+
+//一个三角面 normal 是文件里存的面法线 可能是 0
+#[derive(Copy, Clone)]
+pub(crate) struct StlFacet{
+    pub(crate) normal:Vec3,
+    pub(crate) vertices:[Point3;3],
 }
 
 pub(crate) struct  StlReader{
     buff:Vec<u8>,
     index:usize,
+    facets:Vec<StlFacet>,
 }
 
 impl StlReader{
-    pub(crate) fn new_stl_reader(file_path:String) -> Self{
-        let mut file = File::open(file_path).unwrap();
-        let mut buffer:Vec<u8> = Vec::new();
-        let _res = file.read_to_end(&mut buffer);
-        Self{
+    pub(crate) fn new_stl_reader(file_path:String) -> Result<Self,StlError>{
+        let path = PathBuf::from(file_path);
+        let buffer = fs::read(&path).map_err(|e| StlError::Io(path, e))?;
+        Self::form_bytes(buffer)
+    }
+
+    pub(crate) fn form_bytes(buffer:Vec<u8>) -> Result<Self,StlError>{
+        let mut reader = Self{
             buff: buffer,
-            index: 80
+            index: HEADER_LEN,
+            facets: vec![],
+        };
+        //有些二进制文件头也以 solid 开头 所以先按二进制的大小校验
+        match reader.binary_facet_num() {
+            Some(num) => { reader.read_binary(num) }
+            None if reader.buff.starts_with(b"solid") => {
+                match reader.read_ascii() {
+                    Ok(()) => {}
+                    Err(e) if reader.has_ascii_keywords() => { return Err(e) }
+                    //没有 facet 和 endsolid 是文件头以 solid 开头但大小不对的二进制文件
+                    Err(_) => { return Err(reader.binary_error()) }
+                }
+            }
+            None => { return Err(reader.binary_error()) }
         }
+        Ok(reader)
+    }
+
+    fn has_ascii_keywords(&self) -> bool{
+        String::from_utf8_lossy(&self.buff).lines()
+            .any(|l| matches!(l.split_whitespace().next(), Some("facet") | Some("endsolid")))
+    }
+
+    //按二进制格式解释时的错误
+    fn binary_error(&self) -> StlError{
+        let len = self.buff.len();
+        if len < HEADER_LEN + 4 {
+            return StlError::TooShort{len};
+        }
+        let declared = parse_i32_little_endian(self.buff[HEADER_LEN..HEADER_LEN + 4].to_owned()) as u32 as usize;
+        StlError::SizeMismatch{declared, expected_len: HEADER_LEN + 4 + declared * FACET_LEN, actual_len: len}
+    }
+
+    //文件大小和声明的三角形数量一致时才认为是二进制
+    fn binary_facet_num(&self) -> Option<usize>{
+        if self.buff.len() < HEADER_LEN + 4 {
+            return None;
+        }
+        let declared = parse_i32_little_endian(self.buff[HEADER_LEN..HEADER_LEN + 4].to_owned());
+        if declared < 0 {
+            return None;
+        }
+        let declared = declared as usize;
+        if HEADER_LEN + 4 + declared * FACET_LEN == self.buff.len() {
+            return Some(declared);
+        }
+        None
     }
 
     pub(crate)  fn read_angle_num(&mut self) -> i32{
@@ -42,42 +125,190 @@ impl StlReader{
         parse_f32_little_endian(size_content.to_owned()) as f64
     }
 
+    pub(crate) fn read_vec3(&mut self) -> Vec3{
+        let x = self.read_angle_point();
+        let y = self.read_angle_point();
+        let z = self.read_angle_point();
+        point3!(x,y,z)
+    }
+
     pub(crate)  fn read_angle_info(&mut self){
         self.index += 2;
     }
 
-    pub(crate) fn raed_all_shape_info(&mut self,material:Arc<dyn Materials>) -> Option<BvhNode>{
-        let mut angle = degrees_to_radians(-80.0);
-        let angle_num = self.read_angle_num();
-        println!("三角形数量:{}",angle_num);
-        let mut obj:Vec<Arc<dyn Hittable>> = vec![];
-        for _i in 0..angle_num{
-            let _n_x = self.read_angle_point();
-            let _n_y = self.read_angle_point();
-            let _n_z = self.read_angle_point();
-
-            let t1_x = self.read_angle_point();
-            let t1_y = self.read_angle_point();
-            let t1_z = self.read_angle_point();
-            let t2_x = self.read_angle_point();
-            let t2_y = self.read_angle_point();
-            let t2_z = self.read_angle_point();
-
-            let t3_x = self.read_angle_point();
-            let t3_y = self.read_angle_point();
-            let t3_z = self.read_angle_point();
-            let mut p1 = point3!(t1_x,t1_y,t1_z) ;
-            let mut p2 = point3!(t2_x, t2_y, t2_z);
-            let mut p3 = point3!(t3_x, t3_y, t3_z);
-            let sin_theta = angle.sin();
-            let cos_theta = angle.cos();
-            p1 = Vec3::rotate_x(p1,angle.sin(),angle.cos());
-            p2 = Vec3::rotate_x(p2,sin_theta,cos_theta);
-            p3 = Vec3::rotate_x(p3,sin_theta,cos_theta);
-            obj.push(Arc::new(Triangle::form(p1 * 5.0, p2 * 5.0,p3 * 5.0,material.clone())));
+    //调用前已经用 binary_facet_num 校验过长度 不会越界
+    fn read_binary(&mut self,num:usize){
+        self.index = HEADER_LEN;
+        self.read_angle_num();
+        for _i in 0..num{
+            let normal = self.read_vec3();
+            let p1 = self.read_vec3();
+            let p2 = self.read_vec3();
+            let p3 = self.read_vec3();
+            self.facets.push(StlFacet{ normal, vertices: [p1, p2, p3] });
             self.read_angle_info();
         }
-        let bvh_node = BvhNode::form(obj.as_mut_slice(),0.0001,f64::MAX);
-        bvh_node
+    }
+
+    fn read_ascii(&mut self) -> Result<(),StlError>{
+        let text = String::from_utf8_lossy(&self.buff).into_owned();
+        let mut normal = Vec3::new();
+        let mut vertices = vec![];
+        let mut in_facet = false;
+        let mut ended = false;
+        for (i, raw) in text.lines().enumerate(){
+            let line_no = i + 1;
+            let tokens:Vec<&str> = raw.split_whitespace().collect();
+            if tokens.is_empty() {
+                continue;
+            }
+            match tokens[0] {
+                "facet" => {
+                    if in_facet {
+                        return Err(StlError::Parse{line: line_no, msg: "facet 没有对应的 endfacet".to_string()});
+                    }
+                    in_facet = true;
+                    vertices.clear();
+                    normal = if tokens.len() >= 5 && tokens[1] == "normal" {
+                        Self::parse_ascii_vec3(&tokens[2..5], line_no)?
+                    } else {
+                        Vec3::new()
+                    };
+                }
+                "vertex" => {
+                    if !in_facet || tokens.len() < 4 {
+                        return Err(StlError::Parse{line: line_no, msg: "无效的 vertex".to_string()});
+                    }
+                    vertices.push(Self::parse_ascii_vec3(&tokens[1..4], line_no)?);
+                }
+                "endfacet" => {
+                    if !in_facet || vertices.len() != 3 {
+                        return Err(StlError::Parse{line: line_no, msg: format!("facet 应该有 3 个顶点 实际 {} 个", vertices.len())});
+                    }
+                    self.facets.push(StlFacet{ normal, vertices: [vertices[0], vertices[1], vertices[2]] });
+                    in_facet = false;
+                }
+                "endsolid" => { ended = true; }
+                _ => {}
+            }
+        }
+        if in_facet {
+            return Err(StlError::Parse{line: text.lines().count(), msg: "文件在 facet 中间结束".to_string()});
+        }
+        if !ended {
+            return Err(StlError::Parse{line: text.lines().count(), msg: "缺少 endsolid".to_string()});
+        }
+        Ok(())
+    }
+
+    fn parse_ascii_vec3(tokens:&[&str],line_no:usize) -> Result<Vec3,StlError>{
+        let mut v = Vec3::new();
+        for (i, t) in tokens.iter().enumerate(){
+            let f = t.parse::<f64>().map_err(|_| StlError::Parse{line: line_no, msg: format!("无效的数值 {}", t)})?;
+            v.set_i_field(i as i32, f);
+        }
+        Ok(v)
+    }
+
+    //相同坐标的顶点合并成一个 共享顶点后按 transform 的 crease_angle 计算法线
+    //顶点按调用方给的 transform 变换 不再写死旋转和缩放
+    pub(crate) fn raed_all_shape_info(&mut self,material:Arc<dyn Materials>,transform:&MeshTransform) -> Option<WideBvh>{
+        self.read_mesh(material, transform).build_bvh()
+    }
+
+    //只构建网格 不建 BVH 需要在表面上撒点(比如长毛)时用
+    pub(crate) fn read_mesh(&mut self,material:Arc<dyn Materials>,transform:&MeshTransform) -> TriangleMesh{
+        let mut points = vec![];
+        let mut indices = vec![];
+        let mut welded:HashMap<[u64;3],u32> = HashMap::new();
+        for facet in self.facets.iter(){
+            let [p1, mut p2, mut p3] = facet.vertices;
+            //文件里的面法线不为 0 时 按它修正顶点的绕序 保证叉积得到的是朝外的法线
+            let reversed = !facet.normal.near_zero() && Vec3::dot(Vec3::cross(p2 - p1, p3 - p1), facet.normal) < 0.0;
+            if reversed != transform.flips_winding() {
+                std::mem::swap(&mut p2, &mut p3);
+            }
+            let tri = [p1, p2, p3].map(|p| {
+                *welded.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert_with(|| {
+                    points.push(p);
+                    (points.len() - 1) as u32
+                })
+            });
+            //合并顶点后退化成线段或点的三角形直接丢掉
+            if tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2] {
+                indices.push(tri);
+            }
+        }
+        let mut mesh = TriangleMesh::form(transform.apply(&points), indices, material);
+        mesh.compute_vertex_normals(transform.crease_angle);
+        mesh.refine(transform);
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //一个三角形的二进制 stl header 是 80 字节的文件头
+    fn binary_stl(header:&[u8], declared:u32, facets:usize) -> Vec<u8>{
+        let mut buff = header.to_vec();
+        buff.resize(HEADER_LEN, 0);
+        buff.extend_from_slice(&declared.to_le_bytes());
+        for _i in 0..facets{
+            for v in [[0.0f32, 0.0, 1.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]{
+                for c in v{
+                    buff.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+            buff.extend_from_slice(&[0, 0]);
+        }
+        buff
+    }
+
+    #[test]
+    fn reads_ascii(){
+        let text = "solid test\n facet normal 0 0 1\n  outer loop\n   vertex 0 0 0\n   vertex 1 0 0\n   vertex 0 1 0\n  endloop\n endfacet\nendsolid test\n";
+        let reader = StlReader::form_bytes(text.as_bytes().to_vec()).unwrap();
+        assert_eq!(reader.facets.len(), 1);
+        assert_eq!(reader.facets[0].vertices[1].x, 1.0);
+        assert_eq!(reader.facets[0].normal.z, 1.0);
+    }
+
+    #[test]
+    fn reads_binary(){
+        let reader = StlReader::form_bytes(binary_stl(b"binary", 2, 2)).unwrap();
+        assert_eq!(reader.facets.len(), 2);
+        assert_eq!(reader.facets[1].vertices[2].y, 1.0);
+    }
+
+    #[test]
+    fn binary_with_solid_header_is_read_as_binary(){
+        let reader = StlReader::form_bytes(binary_stl(b"solid exported", 1, 1)).unwrap();
+        assert_eq!(reader.facets.len(), 1);
+    }
+
+    #[test]
+    fn truncated_binary_is_size_mismatch(){
+        let mut buff = binary_stl(b"binary", 2, 2);
+        buff.truncate(buff.len() - 10);
+        assert!(matches!(StlReader::form_bytes(buff), Err(StlError::SizeMismatch{declared: 2, ..})));
+        assert!(matches!(StlReader::form_bytes(b"binary".to_vec()), Err(StlError::TooShort{len: 6})));
+    }
+
+    #[test]
+    fn truncated_binary_with_solid_header_is_size_mismatch(){
+        let mut buff = binary_stl(b"solid exported", 2, 2);
+        buff.truncate(buff.len() - 10);
+        assert!(matches!(StlReader::form_bytes(buff), Err(StlError::SizeMismatch{declared: 2, ..})));
+    }
+
+    #[test]
+    fn ascii_errors_keep_line_numbers(){
+        let text = "solid test\n facet normal 0 0 1\n  vertex 0 0 0\n  vertex 1 0 0\n endfacet\nendsolid test\n";
+        let good = "solid test\n facet normal 0 0 1\n vertex 0 0 0\n vertex 1 0 0\n vertex 0 1 0\n endfacet\n";
+        assert!(matches!(StlReader::form_bytes(text.as_bytes().to_vec()), Err(StlError::Parse{line: 5, ..})));
+        //读出了完整的 facet 但是缺少 endsolid 说明 ascii 文件被截断了
+        assert!(matches!(StlReader::form_bytes(good.as_bytes().to_vec()), Err(StlError::Parse{..})));
     }
 }
@@ -1,7 +1,7 @@
 use crate::ray::Point3;
 use crate::Color;
 use std::sync::Arc;
-use crate::common::Perlin;
+use crate::common::{Perlin, Worley, clamp, cmp_f64, Axis};
 
 pub(crate) trait Texture:Send + Sync{
     fn value(&self,u:f64,v:f64,p:&Point3) -> Color;
@@ -31,30 +31,34 @@ impl Texture for SolidColor{
 }
 
 
+//世界空间的棋盘格 scale 为正弦的频率
 pub(crate) struct CheckerTexture{
     odd:Option<Arc<dyn Texture>>,
     even:Option<Arc<dyn Texture>>,
+    scale:f64,
 }
 
 impl CheckerTexture{
     pub(crate) fn form(even:Arc<dyn Texture>,odd:Arc<dyn Texture>) -> Self{
+        Self::form_scale(even, odd, 10.0)
+    }
+
+    pub(crate) fn form_scale(even:Arc<dyn Texture>,odd:Arc<dyn Texture>,scale:f64) -> Self{
         Self{
             odd: Some(odd),
-            even: Some(even)
+            even: Some(even),
+            scale,
         }
     }
 
     pub(crate) fn form_color(even:Color,odd:Color) -> Self{
-        Self{
-            odd: Some(Arc::new(SolidColor::form_color(odd))),
-            even: Some(Arc::new(SolidColor::form_color(even)))
-        }
+        Self::form(Arc::new(SolidColor::form_color(even)), Arc::new(SolidColor::form_color(odd)))
     }
 }
 
 impl Texture for CheckerTexture{
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
-        let sines = (p.x * 10.0).sin() * (p.y * 10.0).sin() * (p.z * 10.0).sin();
+        let sines = (p.x * self.scale).sin() * (p.y * self.scale).sin() * (p.z * self.scale).sin();
         if sines < 0.0 {
             return self.odd.clone().unwrap().value(u,v,p);
         }
@@ -63,6 +67,39 @@ impl Texture for CheckerTexture{
     }
 }
 
+//UV 空间的棋盘格 u 方向 scale_u 格 v 方向 scale_v 格
+pub(crate) struct UvCheckerTexture{
+    odd:Option<Arc<dyn Texture>>,
+    even:Option<Arc<dyn Texture>>,
+    scale_u:f64,
+    scale_v:f64,
+}
+
+impl UvCheckerTexture{
+    pub(crate) fn form(even:Arc<dyn Texture>,odd:Arc<dyn Texture>,scale_u:f64,scale_v:f64) -> Self{
+        Self{
+            odd: Some(odd),
+            even: Some(even),
+            scale_u,
+            scale_v,
+        }
+    }
+
+    pub(crate) fn form_color(even:Color,odd:Color,scale_u:f64,scale_v:f64) -> Self{
+        Self::form(Arc::new(SolidColor::form_color(even)), Arc::new(SolidColor::form_color(odd)), scale_u, scale_v)
+    }
+}
+
+impl Texture for UvCheckerTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = (u * self.scale_u).floor() as i64 + (v * self.scale_v).floor() as i64;
+        if cell.rem_euclid(2) == 1 {
+            return self.odd.clone().unwrap().value(u,v,p);
+        }
+        self.even.clone().unwrap().value(u,v,p)
+    }
+}
+
 
 pub(crate) struct NoiseTexture{
     noise:Perlin,
@@ -91,4 +128,253 @@ impl Texture for NoiseTexture{
         let p1 = *p * self.scale;
         Color::form(1.0,1.0,1.0) * 0.5 * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p1,7)).sin())
     }
-}
\ No newline at end of file
+}
+
+//分形噪声 输出灰度 [0,1]
+pub(crate) struct FbmTexture{
+    noise:Perlin,
+    scale:f64,
+    octaves:i32,
+    lacunarity:f64,
+    gain:f64,
+}
+
+impl FbmTexture{
+    pub(crate) fn form(scale:f64,octaves:i32) -> Self{
+        Self::form_params(scale, octaves, 2.0, 0.5)
+    }
+
+    pub(crate) fn form_params(scale:f64,octaves:i32,lacunarity:f64,gain:f64) -> Self{
+        Self{
+            noise: Perlin::new(),
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+}
+
+impl Texture for FbmTexture{
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let n = self.noise.fbm(*p * self.scale, self.octaves, self.lacunarity, self.gain);
+        Color::form(1.0,1.0,1.0) * clamp(0.5 * (1.0 + n), 0.0, 1.0)
+    }
+}
+
+
+pub(crate) enum WorleyMode{
+    F1,
+    F2,
+    F2MinusF1,
+}
+
+//细胞噪声 输出灰度
+pub(crate) struct WorleyTexture{
+    noise:Worley,
+    scale:f64,
+    mode:WorleyMode,
+}
+
+impl WorleyTexture{
+    pub(crate) fn form(scale:f64,mode:WorleyMode) -> Self{
+        Self{
+            noise: Worley::new(),
+            scale,
+            mode,
+        }
+    }
+}
+
+impl Texture for WorleyTexture{
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let (f1, f2) = self.noise.noise(&(*p * self.scale));
+        let n = match self.mode {
+            WorleyMode::F1 => { f1 }
+            WorleyMode::F2 => { f2 }
+            WorleyMode::F2MinusF1 => { f2 - f1 }
+        };
+        Color::form(1.0,1.0,1.0) * clamp(n, 0.0, 1.0)
+    }
+}
+
+
+//木纹 以 y 轴为圆心的年轮 用湍流扰动半径
+pub(crate) struct WoodTexture{
+    noise:Perlin,
+    light:Option<Arc<dyn Texture>>,
+    dark:Option<Arc<dyn Texture>>,
+    rings:f64,
+    turbulence:f64,
+}
+
+impl WoodTexture{
+    pub(crate) fn form(light:Arc<dyn Texture>,dark:Arc<dyn Texture>,rings:f64,turbulence:f64) -> Self{
+        Self{
+            noise: Perlin::new(),
+            light: Some(light),
+            dark: Some(dark),
+            rings,
+            turbulence,
+        }
+    }
+
+    pub(crate) fn form_color(light:Color,dark:Color,rings:f64,turbulence:f64) -> Self{
+        Self::form(Arc::new(SolidColor::form_color(light)), Arc::new(SolidColor::form_color(dark)), rings, turbulence)
+    }
+}
+
+impl Texture for WoodTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let r = (p.x * p.x + p.z * p.z).sqrt() * self.rings + self.turbulence * self.noise.turb(*p, 7);
+        let ring = r - r.floor();
+        //smoothstep 让年轮边缘过渡柔和
+        let t = ring * ring * (3.0 - 2.0 * ring);
+        let light = self.light.clone().unwrap().value(u,v,p);
+        let dark = self.dark.clone().unwrap().value(u,v,p);
+        light * (1.0 - t) + dark * t
+    }
+}
+
+
+pub(crate) enum GradientInput{
+    U,
+    V,
+    World(Axis,f64,f64),
+}
+
+//渐变色带 stops 为 (位置, 颜色) 位置在 [0,1] 之间
+pub(crate) struct GradientTexture{
+    stops:Vec<(f64,Color)>,
+    input:GradientInput,
+}
+
+impl GradientTexture{
+    pub(crate) fn form(mut stops:Vec<(f64,Color)>,input:GradientInput) -> Self{
+        if stops.is_empty() {
+            panic!("渐变至少需要一个颜色!")
+        }
+        stops.sort_by(|a, b| cmp_f64(a.0, b.0));
+        Self{
+            stops,
+            input,
+        }
+    }
+
+    fn ramp(&self,t:f64) -> Color{
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2){
+            let (t0, c0) = pair[0];
+            let (t1, c1) = pair[1];
+            if t <= t1 {
+                if t1 - t0 <= 0.0 {
+                    return c1;
+                }
+                let s = (t - t0) / (t1 - t0);
+                return c0 * (1.0 - s) + c1 * s;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+impl Texture for GradientTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let t = match &self.input {
+            GradientInput::U => { u }
+            GradientInput::V => { v }
+            GradientInput::World(axis, min, max) => { (axis.call(*p) - min) / (max - min) }
+        };
+        self.ramp(t)
+    }
+}
+
+
+//按 factor 纹理的亮度在 a 和 b 之间插值
+pub(crate) struct MixTexture{
+    a:Option<Arc<dyn Texture>>,
+    b:Option<Arc<dyn Texture>>,
+    factor:Option<Arc<dyn Texture>>,
+}
+
+impl MixTexture{
+    pub(crate) fn form(a:Arc<dyn Texture>,b:Arc<dyn Texture>,factor:Arc<dyn Texture>) -> Self{
+        Self{
+            a: Some(a),
+            b: Some(b),
+            factor: Some(factor),
+        }
+    }
+
+    pub(crate) fn form_constant(a:Arc<dyn Texture>,b:Arc<dyn Texture>,t:f64) -> Self{
+        Self::form(a, b, Arc::new(SolidColor::form(t, t, t)))
+    }
+}
+
+impl Texture for MixTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let t = clamp(luminance(self.factor.clone().unwrap().value(u,v,p)), 0.0, 1.0);
+        self.a.clone().unwrap().value(u,v,p) * (1.0 - t) + self.b.clone().unwrap().value(u,v,p) * t
+    }
+}
+
+
+pub(crate) struct MultiplyTexture{
+    a:Option<Arc<dyn Texture>>,
+    b:Option<Arc<dyn Texture>>,
+}
+
+impl MultiplyTexture{
+    pub(crate) fn form(a:Arc<dyn Texture>,b:Arc<dyn Texture>) -> Self{
+        Self{
+            a: Some(a),
+            b: Some(b),
+        }
+    }
+}
+
+impl Texture for MultiplyTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.a.clone().unwrap().value(u,v,p) * self.b.clone().unwrap().value(u,v,p)
+    }
+}
+
+
+//把每个通道从 [in_min,in_max] 映射到 [out_min,out_max] 超出部分截断 常用于给灰度噪声上色
+pub(crate) struct RemapTexture{
+    src:Option<Arc<dyn Texture>>,
+    in_min:f64,
+    in_max:f64,
+    out_min:Color,
+    out_max:Color,
+}
+
+impl RemapTexture{
+    pub(crate) fn form(src:Arc<dyn Texture>,in_min:f64,in_max:f64,out_min:Color,out_max:Color) -> Self{
+        Self{
+            src: Some(src),
+            in_min,
+            in_max,
+            out_min,
+            out_max,
+        }
+    }
+}
+
+impl Texture for RemapTexture{
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let c = self.src.clone().unwrap().value(u,v,p);
+        let range = self.in_max - self.in_min;
+        let x = clamp((c.x - self.in_min) / range, 0.0, 1.0);
+        let y = clamp((c.y - self.in_min) / range, 0.0, 1.0);
+        let z = clamp((c.z - self.in_min) / range, 0.0, 1.0);
+        self.out_min + (self.out_max - self.out_min) * Color::form(x, y, z)
+    }
+}
+
+pub(crate) fn luminance(c:Color) -> f64{
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
@@ -0,0 +1,81 @@
+use crate::ray::Ray;
+use crate::hit::{Hittable, HitRecorder};
+use crate::hittable_list::HittableList;
+use crate::bvh::{LinearBvh, BvhConfig};
+use crate::transform::{Mat4, Transform};
+use std::sync::Arc;
+
+//底层加速结构(BLAS): 网格自己的 BVH 或者单个物体 只建一次 多个实例共享
+pub(crate) type Blas = Arc<dyn Hittable>;
+
+//顶层的一个实例 to_world 为 None 时直接用物体本身 不做矩阵变换
+pub(crate) struct Instance{
+    pub(crate) blas:Blas,
+    pub(crate) to_world:Option<Mat4>,
+}
+
+//两层加速结构: 顶层(TLAS)是实例上的 BVH
+//只改实例变换时调用 rebuild 只重建顶层 底层的 BVH 不动
+pub(crate) struct Tlas{
+    instances:Vec<Instance>,
+    bvh:Option<LinearBvh>,
+    //没有包围盒的物体(无限平面)不能放进 BVH 单独逐个求交
+    unbounded:HittableList,
+    config:BvhConfig,
+}
+
+impl Tlas{
+    pub(crate) fn new() -> Self{
+        Self{
+            instances: vec![],
+            bvh: None,
+            unbounded: HittableList::new(),
+            //顶层的实例数量少 每个实例的求交又很贵 叶子只放一个
+            config: BvhConfig{ max_leaf_size: 1, ..BvhConfig::new() },
+        }
+    }
+
+    //返回实例的下标 之后可以用它修改变换
+    pub(crate) fn add(&mut self, blas:Blas) -> usize{
+        self.instances.push(Instance{ blas, to_world: None });
+        self.instances.len() - 1
+    }
+
+    pub(crate) fn add_instance(&mut self, blas:Blas, to_world:Mat4) -> usize{
+        self.instances.push(Instance{ blas, to_world: Some(to_world) });
+        self.instances.len() - 1
+    }
+
+    //修改之后要调用 rebuild 才生效
+    pub(crate) fn set_transform(&mut self, index:usize, to_world:Mat4){
+        self.instances[index].to_world = Some(to_world);
+    }
+
+    pub(crate) fn rebuild(&mut self){
+        let mut bounded:Vec<Arc<dyn Hittable>> = vec![];
+        self.unbounded = HittableList::new();
+        for inst in self.instances.iter(){
+            let obj:Arc<dyn Hittable> = match inst.to_world {
+                None => { inst.blas.clone() }
+                Some(m) => { Arc::new(Transform::form(inst.blas.clone(), m)) }
+            };
+            match obj.bounding_box() {
+                None => { self.unbounded.add(obj) }
+                Some(_) => { bounded.push(obj) }
+            }
+        }
+        self.bvh = LinearBvh::form(bounded.as_mut_slice(), &self.config);
+    }
+
+    pub(crate) fn hit(&self, ray:Ray, t_min:f64, t_max:f64, rec:&mut HitRecorder) -> bool{
+        let mut closest = t_max;
+        let mut is_hit = self.unbounded.hit(ray, t_min, closest, rec);
+        if is_hit {
+            closest = rec.t;
+        }
+        if let Some(ref bvh) = self.bvh {
+            is_hit |= bvh.hit(ray, t_min, closest, rec);
+        }
+        is_hit
+    }
+}
@@ -0,0 +1,473 @@
+use crate::ray::{Point3, Ray};
+use crate::vec3::Vec3;
+use crate::hit::{Hittable, HitRecorder};
+use crate::shape::AABB;
+use crate::common::{Axis, degrees_to_radians, f64_near_zero, cmp_f64, surrounding_box};
+use crate::{point3};
+use crate::precision::gamma;
+use std::fmt::{Debug, Formatter};
+use std::ops::Mul;
+use std::sync::Arc;
+
+//行主序 4x4 矩阵 点按列向量右乘 m * p
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Mat4{
+    pub(crate) m:[[f64;4];4],
+}
+
+impl Mat4{
+    pub(crate) fn identity() -> Self{
+        let mut m = [[0.0;4];4];
+        for (i, row) in m.iter_mut().enumerate(){
+            row[i] = 1.0;
+        }
+        Self{ m }
+    }
+
+    pub(crate) fn translate(offset:Vec3) -> Self{
+        let mut t = Self::identity();
+        t.m[0][3] = offset.x;
+        t.m[1][3] = offset.y;
+        t.m[2][3] = offset.z;
+        t
+    }
+
+    pub(crate) fn scale(s:Vec3) -> Self{
+        let mut t = Self::identity();
+        t.m[0][0] = s.x;
+        t.m[1][1] = s.y;
+        t.m[2][2] = s.z;
+        t
+    }
+
+    //绕任意轴旋转 angle 度 罗德里格斯公式
+    pub(crate) fn rotate(axis:Vec3, angle:f64) -> Self{
+        let a = axis.unit_vector();
+        let radians = degrees_to_radians(angle);
+        let (s, c) = (radians.sin(), radians.cos());
+        let mut t = Self::identity();
+        t.m[0][0] = a.x * a.x + (1.0 - a.x * a.x) * c;
+        t.m[0][1] = a.x * a.y * (1.0 - c) - a.z * s;
+        t.m[0][2] = a.x * a.z * (1.0 - c) + a.y * s;
+        t.m[1][0] = a.x * a.y * (1.0 - c) + a.z * s;
+        t.m[1][1] = a.y * a.y + (1.0 - a.y * a.y) * c;
+        t.m[1][2] = a.y * a.z * (1.0 - c) - a.x * s;
+        t.m[2][0] = a.x * a.z * (1.0 - c) - a.y * s;
+        t.m[2][1] = a.y * a.z * (1.0 - c) + a.x * s;
+        t.m[2][2] = a.z * a.z + (1.0 - a.z * a.z) * c;
+        t
+    }
+
+    //错切 xy 表示 x += xy * y 以此类推
+    pub(crate) fn shear(xy:f64, xz:f64, yx:f64, yz:f64, zx:f64, zy:f64) -> Self{
+        let mut t = Self::identity();
+        t.m[0][1] = xy;
+        t.m[0][2] = xz;
+        t.m[1][0] = yx;
+        t.m[1][2] = yz;
+        t.m[2][0] = zx;
+        t.m[2][1] = zy;
+        t
+    }
+
+    pub(crate) fn transpose(&self) -> Self{
+        let mut t = Self::identity();
+        for i in 0..4{
+            for j in 0..4{
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    //高斯-约旦消元求逆 矩阵奇异时返回 None
+    pub(crate) fn inverse(&self) -> Option<Self>{
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4{
+            let mut pivot = col;
+            for row in col + 1..4{
+                if a[row][col].abs() > a[pivot][col].abs() {
+                    pivot = row;
+                }
+            }
+            if f64_near_zero(a[pivot][col]) {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let d = a[col][col];
+            for j in 0..4{
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for row in 0..4{
+                if row == col {
+                    continue;
+                }
+                let f = a[row][col];
+                for j in 0..4{
+                    a[row][j] -= f * a[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+        Some(Self{ m: inv })
+    }
+
+    pub(crate) fn transform_point(&self, p:Point3) -> Point3{
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            return point3!(x, y, z);
+        }
+        point3!(x / w, y / w, z / w)
+    }
+
+    //方向向量不受平移影响
+    //点 p 带着误差 p_error 变换后的误差上界 仿射变换才用 原来的误差被放大 加上这次变换的舍入
+    pub(crate) fn transform_error(&self, p:Point3, p_error:Vec3) -> Vec3{
+        let m = &self.m;
+        let g = gamma::<f64>(3);
+        let mut out = Vec3::new();
+        for (i, row) in m.iter().take(3).enumerate(){
+            let carried = row[0].abs() * p_error.x + row[1].abs() * p_error.y + row[2].abs() * p_error.z;
+            let rounding = (row[0] * p.x).abs() + (row[1] * p.y).abs() + (row[2] * p.z).abs() + row[3].abs();
+            out.set_i_field(i as i32, carried * (1.0 + g) + rounding * g);
+        }
+        out
+    }
+
+    pub(crate) fn transform_vector(&self, v:Vec3) -> Vec3{
+        let m = &self.m;
+        Vec3::form(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    pub(crate) fn transform_aabb(&self, aabb:AABB) -> AABB{
+        let mut min = point3!(f64::MAX,f64::MAX,f64::MAX);
+        let mut max = point3!(f64::MIN,f64::MIN,f64::MIN);
+        for i in 0..8{
+            let corner = point3!(
+                if i & 1 == 0 { aabb.minimum.x } else { aabb.maximum.x },
+                if i & 2 == 0 { aabb.minimum.y } else { aabb.maximum.y },
+                if i & 4 == 0 { aabb.minimum.z } else { aabb.maximum.z });
+            let p = self.transform_point(corner);
+            min = Vec3::min(min, p);
+            max = Vec3::max(max, p);
+        }
+        AABB::form(min, max)
+    }
+}
+
+//a * b 先做 b 再做 a
+impl Mul for Mat4{
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut m = [[0.0;4];4];
+        for (i, row) in m.iter_mut().enumerate(){
+            for (j, v) in row.iter_mut().enumerate(){
+                *v = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4{ m }
+    }
+}
+
+//用 4x4 矩阵摆放物体 多个 Transform 可以共享同一个物体(比如同一棵网格 BVH)做实例化
+pub(crate) struct Transform{
+    obj_ptr:Option<Arc<dyn Hittable>>,
+    to_world:Mat4,
+    to_object:Mat4,
+    aabb:Option<AABB>,
+}
+
+impl Transform{
+    pub(crate) fn form(p:Arc<dyn Hittable>, to_world:Mat4) -> Self{
+        let to_object = match to_world.inverse() {
+            Some(inv) => { inv }
+            None => { panic!("变换矩阵不可逆!") }
+        };
+        let aabb = p.bounding_box().map(|b| to_world.transform_aabb(b));
+        Self{
+            obj_ptr: Some(p),
+            to_world,
+            to_object,
+            aabb,
+        }
+    }
+}
+
+impl Debug for Transform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"Transform({:?})",self.obj_ptr)
+    }
+}
+
+//射线变到物体空间求交 再把击中点 法线 切线变回世界空间
+fn hit_transformed(obj:&Arc<dyn Hittable>, to_world:&Mat4, to_object:&Mat4, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+    //方向不归一化 物体空间里的 t 和世界空间一致
+    let object_ray = Ray::form_time(to_object.transform_point(ray.origin()),
+                                    to_object.transform_vector(ray.direction()), ray.time());
+    if !obj.hit(object_ray, t_min, t_max, rec) {
+        return false;
+    }
+    let p = rec.p.unwrap();
+    rec.p = Some(to_world.transform_point(p));
+    rec.p_error = rec.p_error.map(|e| to_world.transform_error(p, e));
+    //法线用逆矩阵的转置变换 法线已经朝向射线一侧 变换后和方向的点积符号不变 front_face 不变
+    let normal_matrix = to_object.transpose();
+    rec.normal = Some(normal_matrix.transform_vector(rec.normal.unwrap()).unit_vector());
+    rec.geometric_normal = rec.geometric_normal.map(|n| normal_matrix.transform_vector(n).unit_vector());
+    if let (Some(dpdu), Some(dpdv)) = (rec.tangent, rec.bitangent) {
+        rec.set_tangents(to_world.transform_vector(dpdu), to_world.transform_vector(dpdv));
+    }
+    true
+}
+
+impl Hittable for Transform{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        hit_transformed(self.obj_ptr.as_ref().unwrap(), &self.to_world, &self.to_object, ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        let aabb = self.aabb.unwrap();
+        a.call((aabb.minimum + aabb.maximum) / 2.0)
+    }
+
+    //子物体的 pdf 是物体空间的立体角密度 先按物体空间的距离和夹角换成面积密度
+    //再除以击中点处的面积缩放 最后按世界空间的距离和夹角换回立体角密度 缩放和错切也成立
+    fn pdf_value(&self, rec: &mut HitRecorder, p: Point3, dir: Vec3) -> f64 {
+        let p_object = self.to_object.transform_point(p);
+        //dir 是到光源采样点的向量 长度也要变到物体空间
+        let to_light_object = self.to_object.transform_vector(dir);
+        let pdf_object = self.obj_ptr.as_ref().unwrap().pdf_value(rec, p_object, to_light_object);
+        if pdf_object <= 0.0 {
+            return 0.0;
+        }
+        let hit_object = rec.p.unwrap();
+        let n_object = rec.normal.unwrap().unit_vector();
+        let to_hit_object = hit_object - p_object;
+        let cos_object = Vec3::dot(n_object, to_light_object.unit_vector()).abs();
+        let pdf_area = pdf_object * cos_object / to_hit_object.length_squared();
+        //击中点处单位面积变到世界空间后的面积 两条切线变换后的叉积长度
+        let (t, b) = Vec3::orthonormal_basis(n_object);
+        let area_scale = Vec3::cross(self.to_world.transform_vector(t), self.to_world.transform_vector(b)).length();
+        let to_hit = self.to_world.transform_point(hit_object) - p;
+        let n = self.to_object.transpose().transform_vector(n_object).unit_vector();
+        let cos_theta = Vec3::dot(n, to_hit.unit_vector()).abs();
+        if f64_near_zero(cos_theta) || f64_near_zero(area_scale) {
+            return 0.0;
+        }
+        pdf_area / area_scale * to_hit.length_squared() / cos_theta
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        self.to_world.transform_point(self.obj_ptr.as_ref().unwrap().random_sample())
+    }
+}
+
+
+//动画的一个关键帧 旋转是依次绕 x y z 轴的角度
+#[derive(Copy, Clone)]
+pub(crate) struct Keyframe{
+    pub(crate) time:f64,
+    pub(crate) translate:Vec3,
+    pub(crate) rotate:Vec3,
+    pub(crate) scale:Vec3,
+}
+
+impl Keyframe{
+    pub(crate) fn form(time:f64, translate:Vec3, rotate:Vec3, scale:Vec3) -> Self{
+        Self{
+            time,
+            translate,
+            rotate,
+            scale,
+        }
+    }
+
+    fn lerp(a:&Keyframe, b:&Keyframe, time:f64) -> Keyframe{
+        let s = if b.time > a.time { (time - a.time) / (b.time - a.time) } else { 0.0 };
+        Keyframe{
+            time,
+            translate: a.translate * (1.0 - s) + b.translate * s,
+            rotate: a.rotate * (1.0 - s) + b.rotate * s,
+            scale: a.scale * (1.0 - s) + b.scale * s,
+        }
+    }
+
+    //a 到 b 这一段里包围盒角点运动速度的上界乘以时长
+    //速度 = 平移的速度 + 各轴角速度之和 * 缩放后到原点的距离 + 缩放的速度 * 角点坐标 缩放线性变化 距离取两端的最大值
+    fn max_corner_travel(a:&Keyframe, b:&Keyframe, child_box:AABB) -> f64{
+        let turn = degrees_to_radians((b.rotate - a.rotate).x.abs() + (b.rotate - a.rotate).y.abs() + (b.rotate - a.rotate).z.abs());
+        let mut travel:f64 = 0.0;
+        for i in 0..8{
+            let corner = Vec3::form(
+                if i & 1 == 0 { child_box.minimum.x } else { child_box.maximum.x },
+                if i & 2 == 0 { child_box.minimum.y } else { child_box.maximum.y },
+                if i & 4 == 0 { child_box.minimum.z } else { child_box.maximum.z },
+            );
+            let radius = f64::max((corner * a.scale).length(), (corner * b.scale).length());
+            travel = travel.max((b.translate - a.translate).length() + turn * radius + (corner * (b.scale - a.scale)).length());
+        }
+        travel
+    }
+
+    //先缩放 再旋转 最后平移
+    pub(crate) fn matrix(&self) -> Mat4{
+        Mat4::translate(self.translate) *
+            Mat4::rotate(Vec3::form(0.0, 0.0, 1.0), self.rotate.z) *
+            Mat4::rotate(Vec3::form(0.0, 1.0, 0.0), self.rotate.y) *
+            Mat4::rotate(Vec3::form(1.0, 0.0, 0.0), self.rotate.x) *
+            Mat4::scale(self.scale)
+    }
+}
+
+//每段关键帧之间取多少个时刻计算包围盒
+const BOX_SAMPLES:i32 = 16;
+
+//按射线的时刻在关键帧之间线性插值平移 旋转 缩放 时刻超出范围时停在首尾关键帧
+pub(crate) struct AnimatedTransform{
+    obj_ptr:Option<Arc<dyn Hittable>>,
+    keyframes:Vec<Keyframe>,
+    aabb:Option<AABB>,
+}
+
+impl AnimatedTransform{
+    pub(crate) fn form(p:Arc<dyn Hittable>, mut keyframes:Vec<Keyframe>) -> Self{
+        if keyframes.is_empty() {
+            panic!("动画至少需要一个关键帧!")
+        }
+        keyframes.sort_by(|a, b| cmp_f64(a.time, b.time));
+        let mut animated = Self{
+            obj_ptr: Some(p.clone()),
+            keyframes,
+            aabb: None,
+        };
+        //在每段关键帧之间采样若干时刻 合并包围盒
+        //任意时刻离最近的采样时刻不超过半个间隔 每个采样的包围盒按角点在半个间隔里最多能走多远放大 保证兜住采样之间扫过的部分
+        if let Some(child_box) = p.bounding_box() {
+            let mut total = animated.keyframes[0].matrix().transform_aabb(child_box);
+            for pair in animated.keyframes.windows(2){
+                let pad = Keyframe::max_corner_travel(&pair[0], &pair[1], child_box) / (2 * BOX_SAMPLES) as f64;
+                let pad = Vec3::form(pad, pad, pad);
+                for i in 0 ..= BOX_SAMPLES{
+                    let time = pair[0].time + (pair[1].time - pair[0].time) * i as f64 / BOX_SAMPLES as f64;
+                    let b = animated.matrix_at(time).transform_aabb(child_box);
+                    total = surrounding_box(total, AABB::form(b.minimum - pad, b.maximum + pad)).unwrap();
+                }
+            }
+            animated.aabb = Some(total);
+        }
+        animated
+    }
+
+    //两个关键帧之间的匀速运动
+    pub(crate) fn form_linear(p:Arc<dyn Hittable>, from:Keyframe, to:Keyframe) -> Self{
+        Self::form(p, vec![from, to])
+    }
+
+    fn matrix_at(&self, time:f64) -> Mat4{
+        let frames = &self.keyframes;
+        if time <= frames[0].time {
+            return frames[0].matrix();
+        }
+        for pair in frames.windows(2){
+            if time <= pair[1].time {
+                return Keyframe::lerp(&pair[0], &pair[1], time).matrix();
+            }
+        }
+        frames[frames.len() - 1].matrix()
+    }
+}
+
+impl Debug for AnimatedTransform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"AnimatedTransform({:?})",self.obj_ptr)
+    }
+}
+
+impl Hittable for AnimatedTransform{
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let to_world = self.matrix_at(ray.time());
+        let to_object = match to_world.inverse() {
+            Some(inv) => { inv }
+            None => { return false }
+        };
+        hit_transformed(self.obj_ptr.as_ref().unwrap(), &to_world, &to_object, ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        let aabb = self.aabb.unwrap();
+        a.call((aabb.minimum + aabb.maximum) / 2.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::shape::Sphere;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn animated_bounds_contain_the_whole_sweep(){
+        let child:Arc<dyn Hittable> = Arc::new(Sphere::form(Vec3::form(3.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::form_color(0.5, 0.5, 0.5))));
+        let child_box = child.bounding_box().unwrap();
+        //一段里转了大半圈 采样之间的弧线会跑出采样点的包围盒
+        let animated = AnimatedTransform::form(child, vec![
+            Keyframe::form(0.0, Vec3::new(), Vec3::new(), Vec3::form(1.0, 1.0, 1.0)),
+            Keyframe::form(1.0, Vec3::form(1.0, 2.0, 0.0), Vec3::form(30.0, 300.0, 0.0), Vec3::form(2.0, 1.0, 1.0)),
+        ]);
+        let aabb = animated.bounding_box().unwrap();
+        for i in 0 ..= 10000{
+            let b = animated.matrix_at(i as f64 / 10000.0).transform_aabb(child_box);
+            for k in 0..3{
+                assert!(b.minimum.get_field(k) >= aabb.minimum.get_field(k));
+                assert!(b.maximum.get_field(k) <= aabb.maximum.get_field(k));
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_composed_transform(){
+        let m = Mat4::translate(Vec3::form(1.0, -2.0, 3.0)) * Mat4::rotate(Vec3::form(1.0, 2.0, 0.5), 37.0)
+            * Mat4::shear(0.3, 0.0, -0.2, 0.1, 0.0, 0.4) * Mat4::scale(Vec3::form(2.0, 0.5, 3.0));
+        let inv = m.inverse().unwrap();
+        let identity = Mat4::identity();
+        for p in &[m * inv, inv * m]{
+            for i in 0..4{
+                for j in 0..4{
+                    assert!((p.m[i][j] - identity.m[i][j]).abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse(){
+        assert!(Mat4::scale(Vec3::form(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+}
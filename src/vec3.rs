@@ -2,6 +2,7 @@ use std::f64::consts::PI;
 use std::ops::{Add, Sub, Mul, Neg, AddAssign, MulAssign, DivAssign, Div};
 use crate::ray::Point3;
 use crate::common::{rand_range_f64, rand_f64};
+use crate::sampler::{sample_1d, sample_2d};
 
 
 #[derive(Debug,Copy, Clone)]
@@ -194,6 +195,10 @@ impl Vec3{
         }
     }
 
+    pub(crate) fn abs(self) -> Self{
+        Self{ x: self.x.abs(), y: self.y.abs(), z: self.z.abs() }
+    }
+
     pub(crate) fn near_zero(self) -> bool{
         let s = 1e-8;
         return (self.x).abs() < s && (self.y).abs() < s && (self.z).abs() < s
@@ -210,12 +215,21 @@ impl Vec3{
         return t ;
     }
 
+    //用采样器的一对维度 不能像拒绝采样那样随机用掉好几个维度
     pub(crate) fn random_in_unit_disk() -> Vec3{
-        loop {
-            let p = Vec3::form(rand_range_f64(-1.0,1.0),rand_range_f64(-1.0,1.0),0.0);
-            if p.length_squared() >= 1.0{continue}
-            return p;
-        }
+        let (a, b) = sample_2d();
+        let r = a.sqrt();
+        let phi = 2.0 * PI * b;
+        Vec3::form(r * phi.cos(), r * phi.sin(), 0.0)
+    }
+
+    //单位球内均匀 方向用一对维度 半径用一个维度
+    pub(crate) fn sample_in_unit_sphere() -> Vec3{
+        let (a, b) = sample_2d();
+        let z = 1.0 - 2.0 * a;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * b;
+        Vec3::form(r * phi.cos(), r * phi.sin(), z) * sample_1d().cbrt()
     }
 
     pub(crate) fn min(v1:Vec3,v2:Vec3) -> Vec3{
@@ -279,9 +293,16 @@ impl Vec3{
             z
         }
     }
+    //以 n 为 z 轴构造一组正交基 返回另外两个轴
+    pub(crate) fn orthonormal_basis(n:Vec3) -> (Vec3, Vec3){
+        let a = if n.x.abs() > 0.9 { Vec3::form(0.0,1.0,0.0) } else { Vec3::form(1.0,0.0,0.0) };
+        let t = Vec3::cross(a, n).unit_vector();
+        let b = Vec3::cross(n, t);
+        (t, b)
+    }
+
     pub(crate) fn random_uniform() -> Self{
-        let z = rand_f64();
-        let s = rand_f64();
+        let (z, s) = sample_2d();
         let r = (1.0 - z * z * z).sqrt();
         let phi = 2.0 * PI * s;
         let x = phi.cos() * r;
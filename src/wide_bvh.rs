@@ -0,0 +1,325 @@
+use crate::hit::{Hittable, HitRecorder};
+use crate::ray::{Point3, Ray};
+use crate::shape::AABB;
+use crate::common::Axis;
+use crate::bvh::BvhNode;
+use crate::simd::{F64x4, Vec3x4, dot4, cross4, sub4};
+use crate::vec3::Vec3;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+//每个节点出栈 1 个 最多压入 4 个 遍历栈最多 3 * 深度 + 1 个
+//不超过这个大小时栈放在栈上 更深的树用堆上的栈
+const WIDE_STACK_SIZE:usize = 192;
+//SIMD 只是粗筛 判断放宽一点 边上的交点交给水密的标量求交决定
+const PREFILTER_SLACK:f64 = 1e-6;
+
+#[derive(Debug, Copy, Clone)]
+enum WideChild{
+    Node(u32),
+    Leaf(u32),
+}
+
+//4 叉 BVH 的节点 4 个孩子的包围盒按分量存放 一次测试 4 个
+struct WideNode{
+    min:Vec3x4,
+    max:Vec3x4,
+    children:[Option<WideChild>;4],
+    //有孩子的位
+    valid:u8,
+}
+
+//4 个三角形打包 预先存好 p0 和两条边 ids 是它们在 objects 里的下标
+struct TrianglePacket{
+    p0:Vec3x4,
+    e1:Vec3x4,
+    e2:Vec3x4,
+    ids:[u32;4],
+    valid:u8,
+}
+
+//叶子: 三角形包 和不是三角形的物体 都是连续的一段
+struct WideLeaf{
+    packet_start:u32,
+    packet_count:u32,
+    object_start:u32,
+    object_count:u32,
+}
+
+//把二叉 BVH 每两层合并成一个 4 叉节点 遍历时包围盒和三角形都 4 个一起求交
+pub(crate) struct WideBvh{
+    nodes:Vec<WideNode>,
+    leaves:Vec<WideLeaf>,
+    packets:Vec<TrianglePacket>,
+    objects:Vec<Arc<dyn Hittable>>,
+    bbox:AABB,
+    //4 叉节点最多有几层
+    depth:usize,
+}
+
+fn pack(boxes:&[AABB;4], f:impl Fn(&AABB) -> Vec3) -> Vec3x4{
+    let v = boxes.map(|b| f(&b));
+    [F64x4::form(v.map(|p| p.x)), F64x4::form(v.map(|p| p.y)), F64x4::form(v.map(|p| p.z))]
+}
+
+fn pack_points(points:[Point3;4]) -> Vec3x4{
+    [F64x4::form(points.map(|p| p.x)), F64x4::form(points.map(|p| p.y)), F64x4::form(points.map(|p| p.z))]
+}
+
+fn splat3(v:Vec3) -> Vec3x4{
+    [F64x4::splat(v.x), F64x4::splat(v.y), F64x4::splat(v.z)]
+}
+
+impl WideBvh{
+    pub(crate) fn from_tree(tree:&BvhNode) -> Self{
+        let mut bvh = Self{
+            nodes: vec![],
+            leaves: vec![],
+            packets: vec![],
+            objects: vec![],
+            bbox: tree.bounding_box().unwrap(),
+            depth: 1,
+        };
+        //根节点也是叶子时 包一层只有一个孩子的节点
+        match tree.leaf_objects() {
+            Some(_) => {
+                let leaf = bvh.collapse_leaf(tree);
+                bvh.nodes.push(WideNode{
+                    min: pack(&[bvh.bbox; 4], |b| b.minimum),
+                    max: pack(&[bvh.bbox; 4], |b| b.maximum),
+                    children: [Some(leaf), None, None, None],
+                    valid: 1,
+                });
+            }
+            None => {
+                bvh.collapse(tree, 1);
+            }
+        }
+        bvh
+    }
+
+    //一直展开面积最大的中间孩子 直到凑够 4 个孩子
+    fn collapse(&mut self, node:&BvhNode, depth:usize) -> WideChild{
+        self.depth = self.depth.max(depth);
+        let mut children:Vec<&BvhNode> = vec![node.left.as_ref().unwrap(), node.right.as_ref().unwrap()];
+        while children.len() < 4 {
+            let best = children.iter().enumerate()
+                .filter(|(_, c)| c.leaf_objects().is_none())
+                .max_by(|a, b| {
+                    let sa = a.1.bounding_box().unwrap().surface_area();
+                    let sb = b.1.bounding_box().unwrap().surface_area();
+                    sa.total_cmp(&sb)
+                })
+                .map(|(i, _)| i);
+            match best {
+                None => { break }
+                Some(i) => {
+                    let c = children.swap_remove(i);
+                    children.push(c.left.as_ref().unwrap());
+                    children.push(c.right.as_ref().unwrap());
+                }
+            }
+        }
+
+        let index = self.nodes.len();
+        let mut boxes = [self.bbox; 4];
+        for (i, c) in children.iter().enumerate(){
+            boxes[i] = c.bounding_box().unwrap();
+        }
+        self.nodes.push(WideNode{
+            min: pack(&boxes, |b| b.minimum),
+            max: pack(&boxes, |b| b.maximum),
+            children: [None; 4],
+            valid: (1u8 << children.len()) - 1,
+        });
+        for (i, c) in children.iter().enumerate(){
+            let child = match c.leaf_objects() {
+                Some(_) => { self.collapse_leaf(c) }
+                None => { self.collapse(c, depth + 1) }
+            };
+            self.nodes[index].children[i] = Some(child);
+        }
+        WideChild::Node(index as u32)
+    }
+
+    //三角形 4 个一包 不足 4 个的位置空着
+    fn collapse_leaf(&mut self, node:&BvhNode) -> WideChild{
+        let objs = node.leaf_objects().unwrap();
+        let packet_start = self.packets.len();
+        let mut others:Vec<Arc<dyn Hittable>> = vec![];
+        let mut triangles:Vec<(u32, [Point3;3])> = vec![];
+        for obj in objs.iter(){
+            match obj.triangle() {
+                None => { others.push(obj.clone()) }
+                Some(p) => {
+                    triangles.push((self.objects.len() as u32, p));
+                    self.objects.push(obj.clone());
+                }
+            }
+        }
+        for chunk in triangles.chunks(4){
+            let mut p0 = [Point3::new(); 4];
+            let mut e1 = [Point3::new(); 4];
+            let mut e2 = [Point3::new(); 4];
+            let mut ids = [0u32; 4];
+            for (i, (id, p)) in chunk.iter().enumerate(){
+                p0[i] = p[0];
+                e1[i] = p[1] - p[0];
+                e2[i] = p[2] - p[0];
+                ids[i] = *id;
+            }
+            self.packets.push(TrianglePacket{
+                p0: pack_points(p0),
+                e1: pack_points(e1),
+                e2: pack_points(e2),
+                ids,
+                valid: (1u8 << chunk.len()) - 1,
+            });
+        }
+        let object_start = self.objects.len();
+        self.objects.extend(others.iter().cloned());
+        self.leaves.push(WideLeaf{
+            packet_start: packet_start as u32,
+            packet_count: (self.packets.len() - packet_start) as u32,
+            object_start: object_start as u32,
+            object_count: others.len() as u32,
+        });
+        WideChild::Leaf((self.leaves.len() - 1) as u32)
+    }
+
+    fn hit_leaf(&self, leaf:&WideLeaf, ray:Ray, t_min:f64, closest:&mut f64, rec:&mut HitRecorder) -> bool{
+        let o = splat3(ray.origin());
+        let d = splat3(ray.direction());
+        let mut is_hit = false;
+        let start = leaf.packet_start as usize;
+        for packet in self.packets[start..start + leaf.packet_count as usize].iter(){
+            //Möller-Trumbore 4 个三角形一起算
+            let t1 = sub4(o, packet.p0);
+            let p1 = cross4(d, packet.e2);
+            let p2 = cross4(t1, packet.e1);
+            let det = dot4(p1, packet.e1);
+            let u = dot4(p1, t1) / det;
+            let v = dot4(p2, d) / det;
+            let t = dot4(p2, packet.e2) / det;
+            let zero = F64x4::splat(0.0);
+            let lo = F64x4::splat(-PREFILTER_SLACK);
+            let hi = F64x4::splat(1.0 + PREFILTER_SLACK);
+            let mut mask = packet.valid & (det.gt(zero) | zero.gt(det));
+            mask &= lo.le(u) & u.le(hi) & lo.le(v) & (u + v).le(hi);
+            mask &= F64x4::splat(t_min).le(t) & t.le(F64x4::splat(*closest * (1.0 + PREFILTER_SLACK)));
+            if mask == 0 {
+                continue;
+            }
+            //打中的三角形再用标量求交填写击中记录 按距离从近到远 第一个成功的就是最近的
+            let ts = t.to_array();
+            let mut lanes = [0usize; 4];
+            let mut n = 0;
+            for lane in 0..4{
+                if mask & (1 << lane) != 0 {
+                    lanes[n] = lane;
+                    n += 1;
+                }
+            }
+            lanes[..n].sort_by(|a, b| ts[*a].total_cmp(&ts[*b]));
+            for lane in lanes[..n].iter(){
+                if self.objects[packet.ids[*lane] as usize].hit(ray, t_min, *closest, rec) {
+                    *closest = rec.t;
+                    is_hit = true;
+                    break;
+                }
+            }
+        }
+        let start = leaf.object_start as usize;
+        for obj in self.objects[start..start + leaf.object_count as usize].iter(){
+            if obj.hit(ray, t_min, *closest, rec) {
+                *closest = rec.t;
+                is_hit = true;
+            }
+        }
+        is_hit
+    }
+}
+
+impl Debug for WideBvh {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"WideBvh(nodes: {}, packets: {}, objects: {})",self.nodes.len(),self.packets.len(),self.objects.len())
+    }
+}
+
+impl Hittable for WideBvh {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecorder) -> bool {
+        let dir = ray.direction();
+        let o = splat3(ray.origin());
+        let inv_d = splat3(Vec3::form(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z));
+        let t_lo = F64x4::splat(t_min);
+        let stack_size = 3 * self.depth + 1;
+        let mut small_stack = [(WideChild::Node(0), 0.0); WIDE_STACK_SIZE];
+        let mut large_stack;
+        let stack:&mut [(WideChild, f64)] = if stack_size <= WIDE_STACK_SIZE {
+            &mut small_stack
+        } else {
+            large_stack = vec![(WideChild::Node(0), 0.0); stack_size];
+            &mut large_stack
+        };
+        stack[0] = (WideChild::Node(0), t_min);
+        let mut top = 1;
+        let mut closest = t_max;
+        let mut is_hit = false;
+        while top > 0 {
+            top -= 1;
+            let (child, t_enter) = stack[top];
+            //出栈时已经找到了更近的交点 这个孩子不用看了
+            if t_enter > closest {
+                continue;
+            }
+            match child {
+                WideChild::Leaf(i) => {
+                    is_hit |= self.hit_leaf(&self.leaves[i as usize], ray, t_min, &mut closest, rec);
+                }
+                WideChild::Node(i) => {
+                    let node = &self.nodes[i as usize];
+                    let t_in = [(node.min[0] - o[0]) * inv_d[0], (node.min[1] - o[1]) * inv_d[1], (node.min[2] - o[2]) * inv_d[2]];
+                    let t_out = [(node.max[0] - o[0]) * inv_d[0], (node.max[1] - o[1]) * inv_d[1], (node.max[2] - o[2]) * inv_d[2]];
+                    let t0 = t_in[0].min(t_out[0]).max(t_in[1].min(t_out[1])).max(t_in[2].min(t_out[2]).max(t_lo));
+                    let t1 = t_in[0].max(t_out[0]).min(t_in[1].max(t_out[1])).min(t_in[2].max(t_out[2]).min(F64x4::splat(closest)));
+                    let mask = node.valid & t0.le(t1);
+                    if mask == 0 {
+                        continue;
+                    }
+                    //按进入距离从远到近压栈 近的先出栈
+                    let near = t0.to_array();
+                    let mut hits = [(0usize, 0.0); 4];
+                    let mut n = 0;
+                    for (lane, t) in near.iter().enumerate(){
+                        if mask & (1 << lane) != 0 {
+                            hits[n] = (lane, *t);
+                            n += 1;
+                        }
+                    }
+                    hits[..n].sort_by(|a, b| b.1.total_cmp(&a.1));
+                    for (lane, t) in hits[..n].iter(){
+                        stack[top] = (node.children[*lane].unwrap(), *t);
+                        top += 1;
+                    }
+                }
+            }
+        }
+        is_hit
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    fn get_center_point(&self, a: &Axis) -> f64 {
+        a.call((self.bbox.maximum + self.bbox.minimum) / 2.0)
+    }
+
+    fn pdf_value(&self, _rec: &mut HitRecorder, _p: Point3, _dir: Vec3) -> f64 {
+        todo!()
+    }
+
+    fn random_sample(&self) -> Vec3 {
+        todo!()
+    }
+}